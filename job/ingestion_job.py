@@ -15,7 +15,8 @@ bucket = os.environ['BUCKET']
 print("BUCKET " + bucket)
 
 headers = {'Content-Type': 'application/json'}
-timeout = aiohttp.ClientTimeout(total=79900)
+timeout = aiohttp.ClientTimeout(total=300)
+poll_interval = 5
 
 client = boto3.client(
         "s3"
@@ -30,8 +31,19 @@ async def post_request(file):
     print("Sending " + str(data))
     async with aiohttp.ClientSession(timeout=timeout) as session:
         async with session.post(url, json = data, headers=headers, timeout=timeout) as resp:
-            data = await resp.text()
-            print ("Got Reply: " + data)
+            job = await resp.json()
+            print ("Got Reply: " + str(job))
+
+        # any replica can report the job, the status is saved to the database every second
+        job_url = "{0}/jobs/{1}".format(url, job['job_id'])
+        while job.get('status') in ('running', 'cancelling'):
+            await asyncio.sleep(poll_interval)
+            async with session.get(job_url, timeout=timeout) as resp:
+                if resp.status != 200:
+                    print("Polling {0} returned {1}, retrying".format(job_url, resp.status))
+                    continue
+                job = await resp.json()
+        print ("Job for {0}: {1}".format(file, job))
 
 futures = []
 
@@ -48,5 +60,10 @@ for key in client.list_objects(Bucket=bucket)['Contents']:
 
 loop = asyncio.get_event_loop()
 loop.run_until_complete(asyncio.wait(futures))
+
+# the statistics are computed once over all the files instead of once per job
+stats_url = url.replace("/ingest", "/ingestion/test/stats")
+resp = requests.post(stats_url, params={"wait": "true"})
+print("Stats: " + resp.text)
 print("Took %s" % (time.time() - start_time))
-print("Completed!")
\ No newline at end of file
+print("Completed!")
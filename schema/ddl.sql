@@ -12,4 +12,81 @@ CREATE TABLE IF NOT EXISTS graph.nodes (
    PRIMARY KEY (id, direction, relation, relates_to)
 ) WITH comment = 'Nodes Table' AND caching = {'enabled': 'true'} 
     AND compression = {'sstable_compression': 'LZ4Compressor'}
-    AND CLUSTERING ORDER BY (direction ASC, relation ASC, relates_to DESC);
\ No newline at end of file
+    AND CLUSTERING ORDER BY (direction ASC, relation ASC, relates_to DESC);
+CREATE TABLE IF NOT EXISTS graph.ingestion_nodes (
+   ingestion_id text,
+   id uuid,
+   PRIMARY KEY (ingestion_id, id)
+) WITH comment = 'Nodes of each ingestion'
+    AND compression = {'sstable_compression': 'LZ4Compressor'};
+CREATE TABLE IF NOT EXISTS graph.jobs (
+   id uuid,
+   ingestion_id text,
+   kind text,
+   status text,
+   job text,
+   cancel_requested boolean,
+   PRIMARY KEY (id)
+) WITH comment = 'Status of the jobs, saved by the replica that runs them';
+CREATE INDEX IF NOT EXISTS jobs_by_ingestion ON graph.jobs (ingestion_id);
+CREATE TABLE IF NOT EXISTS graph.aliases (
+   alias text,
+   ingestion_id text,
+   updated_at timestamp,
+   PRIMARY KEY (alias)
+) WITH comment = 'Version of the ingestion used by each alias';
+CREATE TABLE IF NOT EXISTS graph.versions (
+   ingestion_id text,
+   alias text,
+   created_at timestamp,
+   PRIMARY KEY (ingestion_id)
+) WITH comment = 'Ingestion IDs claimed by the loads with an alias';
+CREATE TABLE IF NOT EXISTS graph.node_stats (
+   id uuid,
+   ingestion_id text,
+   children bigint,
+   descendants bigint,
+   depth int,
+   types map<text, bigint>,
+   PRIMARY KEY (id)
+) WITH comment = 'Subtree statistics of each node, computed after an ingestion'
+    AND compression = {'sstable_compression': 'LZ4Compressor'};
+CREATE TABLE IF NOT EXISTS graph.nodes_by_tag (
+   ingestion_id text,
+   tag_type text,
+   tag_value text,
+   item_type text,
+   id uuid,
+   name text,
+   url text,
+   PRIMARY KEY ((ingestion_id, tag_type, tag_value), item_type, id)
+) WITH comment = 'Nodes of each ingestion by tag'
+    AND compression = {'sstable_compression': 'LZ4Compressor'};
+CREATE TABLE IF NOT EXISTS graph.nodes_by_type (
+   ingestion_id text,
+   item_type text,
+   id uuid,
+   name text,
+   url text,
+   PRIMARY KEY ((ingestion_id, item_type), id)
+) WITH comment = 'Nodes of each ingestion by type'
+    AND compression = {'sstable_compression': 'LZ4Compressor'};
+CREATE TABLE IF NOT EXISTS graph.ingestion_types (
+   ingestion_id text,
+   item_type text,
+   nodes bigint,
+   PRIMARY KEY (ingestion_id, item_type)
+) WITH comment = 'Node types of each ingestion and their number of nodes';
+CREATE TABLE IF NOT EXISTS graph.nodes_by_prefix (
+   ingestion_id text,
+   prefix text,
+   exact boolean,
+   depth int,
+   id uuid,
+   name text,
+   url text,
+   item_type text,
+   PRIMARY KEY ((ingestion_id, prefix), exact, depth, id)
+) WITH comment = 'Nodes of each ingestion by prefixes of their name and URL'
+    AND compression = {'sstable_compression': 'LZ4Compressor'}
+    AND CLUSTERING ORDER BY (exact DESC, depth ASC, id ASC);
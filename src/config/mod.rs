@@ -11,11 +11,57 @@ pub struct Config {
     pub host: String,
     pub port: i32,
     pub region: String,
+    pub s3_endpoint: Option<String>,
+    pub local_source_dir: Option<String>,
     pub db_url: String,
     pub db_dc: String,
     pub parallel_files: usize,
     pub db_parallelism: usize,
     pub schema_file: String,
+    #[serde(default = "default_ingestion_batch_size")]
+    pub ingestion_batch_size: usize,
+    #[serde(default = "default_job_retention_secs")]
+    pub job_retention_secs: u64,
+    #[serde(default = "default_db_retries")]
+    pub db_retries: u32,
+    #[serde(default = "default_db_retry_backoff_ms")]
+    pub db_retry_backoff_ms: u64,
+    #[serde(default)]
+    pub db_failure_threshold: f64,
+    #[serde(default = "default_traversal_max_nodes")]
+    pub traversal_max_nodes: usize,
+    #[serde(default = "default_traversal_timeout_ms")]
+    pub traversal_timeout_ms: u64,
+    #[serde(default = "default_traversal_concurrency")]
+    pub traversal_concurrency: usize,
+}
+
+fn default_ingestion_batch_size() -> usize {
+    10000
+}
+
+fn default_job_retention_secs() -> u64 {
+    86400
+}
+
+fn default_db_retries() -> u32 {
+    3
+}
+
+fn default_db_retry_backoff_ms() -> u64 {
+    100
+}
+
+fn default_traversal_max_nodes() -> usize {
+    10000
+}
+
+fn default_traversal_timeout_ms() -> u64 {
+    10000
+}
+
+fn default_traversal_concurrency() -> usize {
+    32
 }
 
 fn init_tracer() {
@@ -1,3 +1,5 @@
 pub mod rest_api;
 pub mod model;
-pub mod source_model;
\ No newline at end of file
+pub mod source_model;
+pub mod source_parser;
+pub mod table_parser;
@@ -1,9 +1,11 @@
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::DIR;
 use crate::db::model::DbNode;
+use crate::db::model::DbNodeStats;
 use crate::db::model::DbRelation;
 
 // UUID struct
@@ -21,6 +23,29 @@ pub struct Node {
     pub node_type: String,
     pub tags: Vec<(String, String)>,
     pub relations: Vec<Relation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<NodeStats>,
+}
+
+/// Statistics of the subtree of a node, computed after the ingestion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeStats {
+    pub children: i64,
+    pub descendants: i64,
+    pub depth: i32,
+    /// Descendants by node type
+    pub types: HashMap<String, i64>,
+}
+
+impl NodeStats {
+    pub fn from(stats: DbNodeStats) -> Self {
+        Self {
+            children: stats.children,
+            descendants: stats.descendants,
+            depth: stats.depth,
+            types: stats.types.unwrap_or_default(),
+        }
+    }
 }
 
 impl Node {
@@ -40,11 +65,14 @@ impl Node {
             node_type,
             tags: attrs,
             relations: vec![],
+            stats: None,
         }
     }
 
+    /// The node of the rows of a partition, `None` if it has no root row: the relations of a
+    /// path that was never ingested as a node don't make a node.
     pub fn from(db_entries: Vec<DbNode>) -> Option<Node> {
-        let mut n = db_entries.get(0)?;
+        let mut n = db_entries.first().filter(|n| n.is_root())?;
         let empty = vec![];
         let attrs = n.tags.as_ref().unwrap_or(&empty);
         let mut node = Node::new(
@@ -68,6 +96,7 @@ impl Node {
                     n.relation.clone().unwrap(),
                     n.relates_to.clone().unwrap(),
                     outbound,
+                    n.tags.clone().unwrap_or_default(),
                 );
                 relations.push(r);
             }
@@ -79,6 +108,18 @@ impl Node {
     }
 }
 
+/// Why a node of a traversal is not expanded, the node is expanded in another place of the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Marker {
+    /// The node is an ancestor of its parent
+    Cycle,
+    /// The node was reached before through another relation
+    Visited,
+    /// The node does not exist, only the relation to it
+    Missing,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TraversalNode {
     pub uuid: Uuid,
@@ -86,8 +127,32 @@ pub struct TraversalNode {
     pub name: String,
     #[serde(rename = "type")]
     pub node_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<(String, String)>>,
+    /// Type and tags of the relation the node was reached from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relation: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub relation_tags: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<Marker>,
+    /// Error reading the node, its relations are unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
     pub relations: Vec<TraversalNode>,
     pub relation_ids: Vec<String>,
+    /// Relations of the node, used to expand it
+    #[serde(skip)]
+    pub relation_edges: Vec<TraversalEdge>,
+}
+
+/// A relation row of a traversed node
+#[derive(Debug, Clone, Default)]
+pub struct TraversalEdge {
+    pub relates_to: Uuid,
+    pub name: String,
+    pub relation: String,
+    pub tags: Vec<(String, String)>,
 }
 
 impl TraversalNode {
@@ -97,23 +162,52 @@ impl TraversalNode {
             depth,
             name,
             node_type,
+            tags: None,
+            relation: None,
+            relation_tags: vec![],
+            marker: None,
+            error: None,
             relations: vec![],
             relation_ids: vec![],
+            relation_edges: vec![],
         }
     }
 
+    /// A node reached through `edge` that is not expanded.
+    pub fn marker(edge: &TraversalEdge, depth: usize, node_type: String, marker: Marker) -> Self {
+        let mut node = TraversalNode::new(edge.relates_to, depth, edge.name.clone(), node_type);
+        node.marker = Some(marker);
+        node
+    }
+
+    /// A node reached through `edge` that could not be read.
+    pub fn failed(edge: &TraversalEdge, depth: usize, error: String) -> Self {
+        let mut node = TraversalNode::new(edge.relates_to, depth, edge.name.clone(), String::new());
+        node.error = Some(error);
+        node
+    }
+
+    /// `None` without the root row, like `Node::from`.
     pub fn from(db_entries: Vec<DbRelation>, depth: usize) -> Option<TraversalNode> {
-        let n = db_entries.get(0)?;
+        let n = db_entries
+            .first()
+            .filter(|n| n.direction.as_deref().unwrap_or_default().is_empty())?;
         let mut node = TraversalNode::new(n.uuid, depth, n.name.clone(), n.node_type.clone());
+        node.tags = Some(n.tags.clone().unwrap_or_default());
 
-        let mut ids = vec![];
-        for i in 1..db_entries.len() {
-            let r = db_entries.get(i)?;
-            ids.push(r.relates_to.clone().unwrap());
+        for r in db_entries.iter().skip(1) {
+            let relates_to = r.relates_to.clone().unwrap_or_default();
+            if let Ok(id) = Uuid::parse_str(&relates_to) {
+                node.relation_edges.push(TraversalEdge {
+                    relates_to: id,
+                    name: r.name.clone(),
+                    relation: r.relation.clone().unwrap_or_default(),
+                    tags: r.tags.clone().unwrap_or_default(),
+                });
+            }
+            node.relation_ids.push(relates_to);
         }
 
-        node.relation_ids = ids;
-
         Some(node)
     }
 }
@@ -125,6 +219,11 @@ pub fn get_id_from_url(ingestion_id: String, url: String) -> Uuid {
     uuid
 }
 
+/// Id of the node of a path sent by a client, like `/root/children1/children11/`
+pub fn get_id_from_path(ingestion_id: &str, path: &str) -> Uuid {
+    get_id_from_url(ingestion_id.to_owned(), path.trim_matches('/').to_owned())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Relation {
     #[serde(rename = "type")]
@@ -132,10 +231,17 @@ pub struct Relation {
     pub outbound: bool,
     pub target_name: String,
     pub relates_to: String,
+    pub tags: Vec<(String, String)>,
 }
 
 impl Relation {
-    pub fn new(ingestion_id: String, rel_type: String, url: String, outbound: bool) -> Self {
+    pub fn new(
+        ingestion_id: String,
+        rel_type: String,
+        url: String,
+        outbound: bool,
+        tags: Vec<(String, String)>,
+    ) -> Self {
         let names: Vec<&str> = url.split('/').collect();
         let name = names[names.len() - 1].to_owned();
         Self {
@@ -143,14 +249,88 @@ impl Relation {
             target_name: name,
             relates_to: get_id_from_url(ingestion_id, url).to_string(),
             outbound,
+            tags,
         }
     }
-    pub fn from(name: String, rel_type: String, relates_to: String, outbound: bool) -> Self {
+    pub fn from(
+        name: String,
+        rel_type: String,
+        relates_to: String,
+        outbound: bool,
+        tags: Vec<(String, String)>,
+    ) -> Self {
         Self {
             rel_type,
             target_name: name,
             relates_to,
             outbound,
+            tags,
         }
     }
+
+    pub fn from_db(r: DbRelation) -> Self {
+        let outbound = r.direction.as_deref() == Some(DIR::OUT.to_string().as_str());
+        Relation::from(
+            r.name,
+            r.relation.unwrap_or_default(),
+            r.relates_to.unwrap_or_default(),
+            outbound,
+            r.tags.unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relation(uuid: Uuid, direction: &str) -> DbNode {
+        DbNode::relation(
+            uuid,
+            "test".to_owned(),
+            direction.to_owned(),
+            "custom".to_owned(),
+            Uuid::nil().to_string(),
+            "other".to_owned(),
+        )
+    }
+
+    #[test]
+    fn from_reads_the_root_and_the_relations() {
+        let root = DbNode::root("test".to_owned(), "a".to_owned(), "a".to_owned(), "t".to_owned(), vec![]);
+        let id = root.uuid;
+        let node = Node::from(vec![root, relation(id, "IN"), relation(id, "OUT")]).unwrap();
+
+        assert_eq!(node.uuid, id);
+        assert_eq!(node.name, "a");
+        assert_eq!(node.relations.len(), 2);
+        assert!(!node.relations[0].outbound);
+        assert!(node.relations[1].outbound);
+    }
+
+    #[test]
+    fn from_requires_the_root_row() {
+        let id = get_id_from_url("test".to_owned(), "a".to_owned());
+        assert!(Node::from(vec![relation(id, "OUT")]).is_none());
+        assert!(Node::from(vec![]).is_none());
+    }
+
+    #[test]
+    fn get_id_from_path_is_the_id_of_the_ingested_node() {
+        // the id the ingestion gives to the node root/children1
+        let ingested = DbNode::root(
+            "test".to_owned(),
+            "root/children1".to_owned(),
+            "children1".to_owned(),
+            "t".to_owned(),
+            vec![],
+        )
+        .uuid;
+
+        assert_eq!(ingested, get_id_from_url("test".to_owned(), "root/children1".to_owned()));
+        assert_eq!(get_id_from_path("test", "root/children1"), ingested);
+        assert_eq!(get_id_from_path("test", "/root/children1"), ingested);
+        assert_eq!(get_id_from_path("test", "/root/children1/"), ingested);
+        assert_ne!(get_id_from_path("other", "/root/children1"), ingested);
+    }
 }
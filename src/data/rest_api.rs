@@ -1,33 +1,256 @@
 use serde::Serialize;
 use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::data::model::{Marker, Node, Relation, TraversalNode};
+use crate::data::source_model::{ColumnMapping, SourceFormat};
+use crate::jobs::model::JobStatus;
 
 #[derive(Serialize, Deserialize)]
 pub struct IngestionRequest {
     pub ingestion_id: String,
-    pub files: Vec<String>
+    pub files: Vec<String>,
+    /// Edges tables, for CSV and Parquet files
+    #[serde(default)]
+    pub edges: Vec<String>,
+    pub format: Option<SourceFormat>,
+    #[serde(default)]
+    pub columns: ColumnMapping,
+    /// Alias switched to this ingestion once all the files are ingested
+    pub alias: Option<String>,
+    /// Computes the subtree statistics of the nodes once the files are ingested
+    pub stats: Option<bool>,
+    pub wait: Option<bool>
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(transparent)]
-pub struct File {
-    pub files: Vec<String>
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct DeleteIngestionRequest {
+    pub wait: Option<bool>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct StatsRequest {
+    pub wait: Option<bool>
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestionResponse {
+    pub job_id: Uuid,
+    pub status: JobStatus
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct ResolvePathsRequest {
+    pub paths: Vec<String>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct BatchGetRequest {
+    pub ids: Vec<String>,
+    pub get_tags: Option<bool>,
+    pub get_relations: Option<bool>,
+    pub get_stats: Option<bool>
+}
+
+/// Result of each id of a batch get
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum NodeResult {
+    Found { node: Box<Node> },
+    NotFound,
+    Error { message: String }
 }
 
+#[derive(Debug, Serialize)]
+pub struct AliasResponse {
+    pub alias: String,
+    pub ingestion_id: String
+}
 
 #[derive(Debug, Serialize)]
 pub struct AppError {
     message: String
 }
 
+impl AppError {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize, Default)]
 pub struct GetNodeRequest {
     pub get_tags: Option<bool>,
-    pub get_relations: Option<bool>
+    pub get_relations: Option<bool>,
+    pub get_stats: Option<bool>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct RelationsRequest {
+    pub direction: Option<String>,
+    pub relation_type: Option<String>,
+    pub page_size: Option<usize>,
+    /// Cursor of the page, from the previous response
+    pub cursor: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelationsPage {
+    pub relations: Vec<Relation>,
+    /// Cursor of the next page, None in the last page
+    pub cursor: Option<String>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct ExportRequest {
+    pub max_depth: Option<usize>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct PageRequest {
+    pub page_size: Option<usize>,
+    /// Cursor of the page, from the previous response
+    pub cursor: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeCount {
+    #[serde(rename = "type")]
+    pub node_type: String,
+    pub nodes: i64
+}
+
+/// Type-ahead search of the nodes of an ingestion, or of the version an alias points to
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct SearchRequest {
+    pub q: String,
+    pub ingestion_id: Option<String>,
+    pub alias: Option<String>,
+    pub limit: Option<usize>
+}
+
+/// Nodes of an ingestion, or of the version an alias points to, with a tag
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct TagSearchRequest {
+    pub ingestion_id: Option<String>,
+    pub alias: Option<String>,
+    #[serde(rename = "type")]
+    pub tag_type: String,
+    pub value: String,
+    pub node_type: Option<String>,
+    pub page_size: Option<usize>,
+    /// Cursor of the page, from the previous response
+    pub cursor: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+pub struct NodesPage {
+    pub nodes: Vec<Node>,
+    /// Cursor of the next page, None in the last page
+    pub cursor: Option<String>
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize, Default)]
 pub struct TraversalNodeRequest {
     pub direction: String,
     pub relation_type: Option<String>,
-    pub max_depth: usize
+    pub max_depth: usize,
+    pub max_nodes: Option<usize>,
+    pub timeout_ms: Option<u64>,
+    pub strict: Option<bool>,
+    #[serde(default)]
+    pub format: TraversalFormat,
+    pub get_tags: Option<bool>
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraversalFormat {
+    /// Nested `TraversalNode`s, the root node is the response
+    #[default]
+    Tree,
+    /// Nested `TraversalNode`s with the number of nodes and errors and why it was truncated
+    TreeSummary,
+    /// Lists of nodes and edges
+    Flat
+}
+
+/// Why a traversal stopped before reaching `max_depth`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    MaxNodes,
+    Timeout
+}
+
+#[derive(Debug, Serialize)]
+pub struct TraversalResponse {
+    pub root: Option<TraversalNode>,
+    /// Nodes read
+    pub nodes: usize,
+    /// Nodes that could not be read
+    pub errors: usize,
+    pub truncated: Option<Truncation>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct AncestorsRequest {
+    /// Resolve the ancestors from the URL of the node in one batch
+    pub from_url: Option<bool>,
+    pub get_tags: Option<bool>
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, Default)]
+pub struct PathRequest {
+    pub from: String,
+    pub to: String,
+    pub direction: Option<String>,
+    pub relation_type: Option<String>,
+    pub max_depth: Option<usize>,
+    pub max_nodes: Option<usize>,
+    pub timeout_ms: Option<u64>
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathResponse {
+    /// Number of relations of the path
+    pub length: usize,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<FlatEdge>
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlatNode {
+    pub uuid: Uuid,
+    pub depth: usize,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<(String, String)>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<Marker>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlatEdge {
+    pub source: Uuid,
+    pub target: Uuid,
+    pub relation: String,
+    pub direction: String,
+    /// Depth of the target
+    pub depth: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<(String, String)>
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlatTraversalResponse {
+    pub nodes: Vec<FlatNode>,
+    pub edges: Vec<FlatEdge>,
+    /// Nodes that could not be read
+    pub errors: usize,
+    pub truncated: Option<Truncation>
 }
\ No newline at end of file
@@ -2,6 +2,9 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+// Files are parsed incrementally by `source_parser`, never as a whole.
+// A delta file is a file whose nodes and relations set their `op`.
+#[allow(dead_code)]
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct File {
@@ -9,12 +12,37 @@ pub struct File {
     pub relations: Vec<Relation>,
 }
 
+/// Change of a node or relation in a delta, `added` and `updated` are both upserts
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    #[default]
+    Added,
+    Updated,
+    Removed,
+}
+
+impl Op {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "added" => Some(Op::Added),
+            "updated" => Some(Op::Updated),
+            "removed" => Some(Op::Removed),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Nodes {
     pub name: String,
     #[serde(rename = "type")]
     pub type_field: String,
+    /// Before the children, the parser streams them and a removed op applies to all of them
+    #[serde(default)]
+    pub op: Op,
+    #[serde(default)]
     pub children: Vec<Nodes>,
     pub tags: Option<Vec<Tag>>,
     pub total_children: Option<i64>,
@@ -36,4 +64,143 @@ pub struct Relation {
     pub source: Vec<String>,
     pub target: Vec<String>,
     pub tags: Option<Vec<Tag>>,
+    #[serde(default)]
+    pub op: Op,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceFormat {
+    /// A single `File` document with nested children
+    Json,
+    /// One `Record` per line
+    Ndjson,
+    /// A nodes or edges table
+    Csv,
+    /// A nodes or edges table
+    Parquet,
+}
+
+impl SourceFormat {
+    /// Detects the format from the extension, ignoring the compression extension.
+    pub fn detect(path: &str) -> Self {
+        let path = path.to_lowercase();
+        let path = [".gz", ".gzip", ".zst", ".zstd", ".bz2"]
+            .iter()
+            .find_map(|ext| path.strip_suffix(ext))
+            .unwrap_or(&path);
+        if path.ends_with(".ndjson") || path.ends_with(".jsonl") {
+            SourceFormat::Ndjson
+        } else if path.ends_with(".csv") {
+            SourceFormat::Csv
+        } else if path.ends_with(".parquet") {
+            SourceFormat::Parquet
+        } else {
+            SourceFormat::Json
+        }
+    }
+}
+
+/// A line of a NDJSON file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Record {
+    Node(NodeRecord),
+    Relation(Relation),
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    /// Path of the parent node, empty for root nodes
+    #[serde(default)]
+    pub parent: Vec<String>,
+    pub tags: Option<Vec<Tag>>,
+    #[serde(default)]
+    pub op: Op,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableKind {
+    Nodes,
+    Edges,
+}
+
+/// Columns of the nodes and edges tables of CSV and Parquet files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    /// Path of the node. If `name` is set, it is the path of the parent
+    pub path: String,
+    pub path_separator: String,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    /// Columns stored as tags, the column name is the tag type
+    pub tags: Vec<String>,
+    pub source: String,
+    pub target: String,
+    pub relation_type: String,
+    pub relation_tags: Vec<String>,
+    /// Column with the `Op` of the row, for delta tables
+    pub op: Option<String>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            path: "path".to_owned(),
+            path_separator: "/".to_owned(),
+            name: None,
+            type_field: "type".to_owned(),
+            tags: vec![],
+            source: "source".to_owned(),
+            target: "target".to_owned(),
+            relation_type: "type".to_owned(),
+            relation_tags: vec![],
+            op: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_format_from_the_extension() {
+        assert_eq!(SourceFormat::detect("s3://bucket/a.json"), SourceFormat::Json);
+        assert_eq!(SourceFormat::detect("s3://bucket/a"), SourceFormat::Json);
+        assert_eq!(SourceFormat::detect("s3://bucket/a.ndjson"), SourceFormat::Ndjson);
+        assert_eq!(SourceFormat::detect("s3://bucket/a.JSONL"), SourceFormat::Ndjson);
+        assert_eq!(SourceFormat::detect("file:///a.csv"), SourceFormat::Csv);
+        assert_eq!(SourceFormat::detect("file:///a.parquet"), SourceFormat::Parquet);
+    }
+
+    #[test]
+    fn detect_format_ignores_the_compression_extension() {
+        assert_eq!(SourceFormat::detect("s3://bucket/a.jsonl.gz"), SourceFormat::Ndjson);
+        assert_eq!(SourceFormat::detect("s3://bucket/a.ndjson.zst"), SourceFormat::Ndjson);
+        assert_eq!(SourceFormat::detect("s3://bucket/a.csv.bz2"), SourceFormat::Csv);
+        assert_eq!(SourceFormat::detect("s3://bucket/a.json.gz"), SourceFormat::Json);
+    }
+
+    #[test]
+    fn record_kind() {
+        let record: Record =
+            serde_json::from_str(r#"{"kind": "node", "name": "a", "type": "t"}"#).unwrap();
+        assert_eq!(
+            record,
+            Record::Node(NodeRecord {
+                name: "a".to_owned(),
+                type_field: "t".to_owned(),
+                ..Default::default()
+            })
+        );
+        assert!(serde_json::from_str::<Record>(r#"{"name": "a", "type": "t"}"#).is_err());
+    }
 }
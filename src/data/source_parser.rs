@@ -0,0 +1,435 @@
+// Incremental parser for the SOURCE SCHEMA
+use crate::data::source_model::{
+    ColumnMapping, NodeRecord, Nodes, Op, Record, Relation, SourceFormat, TableKind, Tag,
+};
+use crate::data::table_parser::{parse_csv, parse_parquet};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserializer;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use tokio::sync::mpsc::Sender;
+
+#[derive(Debug, PartialEq)]
+pub enum SourceItem {
+    NodeRecord(NodeRecord),
+    Relation(Relation),
+}
+
+/// Parses a file of any format, `kind` and `columns` are only used by the table formats.
+pub fn parse<R: Read>(
+    format: SourceFormat,
+    kind: TableKind,
+    columns: &ColumnMapping,
+    reader: R,
+    tx: Sender<SourceItem>,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    match format {
+        SourceFormat::Json => parse_file(reader, tx)?,
+        SourceFormat::Ndjson => parse_ndjson(reader, tx)?,
+        SourceFormat::Csv => parse_csv(kind, columns, reader, tx)?,
+        SourceFormat::Parquet => parse_parquet(kind, columns, reader, tx)?,
+    }
+    Ok(())
+}
+
+/// Parses a NDJSON file sending every record to `tx` as soon as its line is read.
+/// Blocks on the channel, call it from a blocking task.
+pub fn parse_ndjson<R: Read>(reader: R, tx: Sender<SourceItem>) -> Result<(), serde_json::Error> {
+    let records = serde_json::Deserializer::from_reader(reader).into_iter::<Record>();
+    for record in records {
+        let item = match record? {
+            Record::Node(node) => SourceItem::NodeRecord(node),
+            Record::Relation(relation) => SourceItem::Relation(relation),
+        };
+        tx.blocking_send(item)
+            .map_err(|_| de::Error::custom("item receiver closed"))?;
+    }
+    Ok(())
+}
+
+/// Parses a source `File` document sending every node, at any depth, and every relation
+/// to `tx` as soon as it is deserialized, so the whole file is never in memory.
+/// Nodes are sent as a `NodeRecord` with the path of their parent, like the lines of a
+/// NDJSON file, after their children. Blocks on the channel, call it from a blocking task.
+pub fn parse_file<R: Read>(reader: R, tx: Sender<SourceItem>) -> Result<(), serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.deserialize_map(FileVisitor { tx: &tx })?;
+    deserializer.end()
+}
+
+struct FileVisitor<'a> {
+    tx: &'a Sender<SourceItem>,
+}
+
+impl<'de, 'a> Visitor<'de> for FileVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a file with nodes and relations")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "nodes" => map.next_value_seed(NodesSeed {
+                    tx: self.tx,
+                    parent: &[],
+                    removed: false,
+                })?,
+                "relations" => map.next_value_seed(ItemsSeed {
+                    tx: self.tx,
+                    item: SourceItem::Relation,
+                })?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ItemsSeed<'a, T> {
+    tx: &'a Sender<SourceItem>,
+    item: fn(T) -> SourceItem,
+}
+
+impl<'de, 'a, T: de::Deserialize<'de>> DeserializeSeed<'de> for ItemsSeed<'a, T> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, T: de::Deserialize<'de>> Visitor<'de> for ItemsSeed<'a, T> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of nodes or relations")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(value) = seq.next_element::<T>()? {
+            self.tx
+                .blocking_send((self.item)(value))
+                .map_err(|_| de::Error::custom("item receiver closed"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The `children` of a node, or the top level nodes.
+/// `removed` is set when the parent is removed, the whole subtree is removed.
+struct NodesSeed<'a> {
+    tx: &'a Sender<SourceItem>,
+    parent: &'a [String],
+    removed: bool,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for NodesSeed<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for NodesSeed<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq
+            .next_element_seed(NodeSeed {
+                tx: self.tx,
+                parent: self.parent,
+                removed: self.removed,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+/// A node, its children are streamed if its `name` comes before them. Otherwise the path of
+/// the children is not known when they are read and they are kept in memory until the end of
+/// the node. A `removed` op after streamed children fails the file, they were already sent
+/// as upserts.
+struct NodeSeed<'a> {
+    tx: &'a Sender<SourceItem>,
+    parent: &'a [String],
+    removed: bool,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for NodeSeed<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for NodeSeed<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut name: Option<String> = None;
+        let mut type_field: Option<String> = None;
+        let mut tags: Option<Vec<Tag>> = None;
+        let mut op: Option<Op> = None;
+        let mut buffered: Vec<Nodes> = vec![];
+        let mut streamed = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "name" => name = Some(map.next_value()?),
+                "type" => type_field = Some(map.next_value()?),
+                "tags" => tags = map.next_value()?,
+                "op" => {
+                    let value: Op = map.next_value()?;
+                    if streamed && value == Op::Removed && !self.removed {
+                        return Err(de::Error::custom(format!(
+                            "the removed op of node {} must come before its children",
+                            name.as_deref().unwrap_or_default()
+                        )));
+                    }
+                    op = Some(value);
+                }
+                "children" => match &name {
+                    Some(name) => {
+                        streamed = true;
+                        let path = child_path(self.parent, name);
+                        map.next_value_seed(NodesSeed {
+                            tx: self.tx,
+                            parent: &path,
+                            removed: self.removed || op == Some(Op::Removed),
+                        })?
+                    }
+                    None => buffered = map.next_value()?,
+                },
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let name = name.ok_or_else(|| de::Error::missing_field("name"))?;
+        let type_field = type_field.ok_or_else(|| de::Error::missing_field("type"))?;
+        let op = if self.removed { Op::Removed } else { op.unwrap_or_default() };
+        let path = child_path(self.parent, &name);
+        send_nodes(self.tx, &path, op == Op::Removed, buffered).map_err(de::Error::custom)?;
+        send_node(
+            self.tx,
+            NodeRecord {
+                name,
+                type_field,
+                parent: self.parent.to_vec(),
+                tags,
+                op,
+            },
+        )
+        .map_err(de::Error::custom)
+    }
+}
+
+fn child_path(parent: &[String], name: &str) -> Vec<String> {
+    let mut path = parent.to_vec();
+    path.push(name.to_owned());
+    path
+}
+
+/// Sends the nodes that were kept in memory, the children first like `NodeSeed`.
+fn send_nodes(
+    tx: &Sender<SourceItem>,
+    parent: &[String],
+    removed: bool,
+    nodes: Vec<Nodes>,
+) -> Result<(), &'static str> {
+    for node in nodes {
+        let op = if removed { Op::Removed } else { node.op };
+        let path = child_path(parent, &node.name);
+        send_nodes(tx, &path, op == Op::Removed, node.children)?;
+        send_node(
+            tx,
+            NodeRecord {
+                name: node.name,
+                type_field: node.type_field,
+                parent: parent.to_vec(),
+                tags: node.tags,
+                op,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn send_node(tx: &Sender<SourceItem>, node: NodeRecord) -> Result<(), &'static str> {
+    tx.blocking_send(SourceItem::NodeRecord(node))
+        .map_err(|_| "item receiver closed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn collect(
+        parser: impl FnOnce(Sender<SourceItem>) -> Result<(), serde_json::Error>,
+    ) -> (Vec<NodeRecord>, Vec<Relation>) {
+        let (tx, mut rx) = mpsc::channel(1024);
+        parser(tx).unwrap();
+        let (mut nodes, mut relations) = (vec![], vec![]);
+        while let Ok(item) = rx.try_recv() {
+            match item {
+                SourceItem::NodeRecord(node) => nodes.push(node),
+                SourceItem::Relation(relation) => relations.push(relation),
+            }
+        }
+        (nodes, relations)
+    }
+
+    fn parse_json(json: &str) -> Vec<NodeRecord> {
+        collect(|tx| parse_file(json.as_bytes(), tx)).0
+    }
+
+    fn node(name: &str, parent: &[&str], op: Op) -> NodeRecord {
+        NodeRecord {
+            name: name.to_owned(),
+            type_field: "t".to_owned(),
+            parent: parent.iter().map(|p| p.to_string()).collect(),
+            tags: None,
+            op,
+        }
+    }
+
+    #[test]
+    fn parse_file_sends_the_same_records_as_ndjson() {
+        let json = std::fs::read("data/data_example.json").unwrap();
+        let ndjson = std::fs::read("data/data_example.ndjson").unwrap();
+        let (mut nodes, relations) = collect(|tx| parse_file(json.as_slice(), tx));
+        let (mut expected, expected_relations) = collect(|tx| parse_ndjson(ndjson.as_slice(), tx));
+
+        let key = |n: &NodeRecord| (n.parent.clone(), n.name.clone());
+        nodes.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(nodes.len(), 18);
+        assert_eq!(nodes, expected);
+        assert_eq!(relations, expected_relations);
+    }
+
+    #[test]
+    fn parse_file_sends_children_before_their_parent() {
+        let nodes = parse_json(
+            r#"{"nodes": [{"name": "a", "type": "t", "totalChildren": 1,
+                "children": [{"name": "b", "type": "t"}]}]}"#,
+        );
+        assert_eq!(nodes, vec![node("b", &["a"], Op::Added), node("a", &[], Op::Added)]);
+    }
+
+    #[test]
+    fn parse_file_keeps_children_read_before_the_name() {
+        let nodes = parse_json(
+            r#"{"nodes": [{"children": [{"name": "b", "type": "t",
+                "children": [{"name": "c", "type": "t"}]}], "name": "a", "type": "t"}]}"#,
+        );
+        assert_eq!(
+            nodes,
+            vec![
+                node("c", &["a", "b"], Op::Added),
+                node("b", &["a"], Op::Added),
+                node("a", &[], Op::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_file_removes_the_subtree_of_a_removed_node() {
+        let nodes = parse_json(
+            r#"{"nodes": [{"name": "a", "type": "t", "op": "removed",
+                "children": [{"name": "b", "type": "t", "op": "updated"}]},
+                {"children": [{"name": "d", "type": "t"}], "name": "c", "type": "t", "op": "removed"}]}"#,
+        );
+        assert_eq!(
+            nodes,
+            vec![
+                node("b", &["a"], Op::Removed),
+                node("a", &[], Op::Removed),
+                node("d", &["c"], Op::Removed),
+                node("c", &[], Op::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_file_fails_on_a_removed_op_after_the_children() {
+        let (tx, _rx) = mpsc::channel(16);
+        let json = r#"{"nodes": [{"name": "a", "type": "t",
+            "children": [{"name": "b", "type": "t"}], "op": "removed"}]}"#;
+        let error = parse_file(json.as_bytes(), tx).unwrap_err();
+        assert!(error.to_string().contains("must come before its children"));
+
+        // other ops don't change the children
+        let nodes = parse_json(
+            r#"{"nodes": [{"name": "a", "type": "t",
+                "children": [{"name": "b", "type": "t"}], "op": "updated"}]}"#,
+        );
+        assert_eq!(nodes, vec![node("b", &["a"], Op::Added), node("a", &[], Op::Updated)]);
+    }
+
+    #[test]
+    fn parse_file_reads_the_removed_nodes_it_serializes() {
+        let file = crate::data::source_model::File {
+            nodes: vec![Nodes {
+                name: "a".to_owned(),
+                type_field: "t".to_owned(),
+                op: Op::Removed,
+                children: vec![Nodes {
+                    name: "b".to_owned(),
+                    type_field: "t".to_owned(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            relations: vec![],
+        };
+        let json = serde_json::to_string(&file).unwrap();
+        assert_eq!(
+            parse_json(&json),
+            vec![node("b", &["a"], Op::Removed), node("a", &[], Op::Removed)]
+        );
+    }
+
+    #[test]
+    fn parse_file_fails_without_name() {
+        let (tx, _rx) = mpsc::channel(16);
+        let error = parse_file(r#"{"nodes": [{"type": "t"}]}"#.as_bytes(), tx).unwrap_err();
+        assert!(error.to_string().contains("missing field `name`"));
+    }
+
+    #[test]
+    fn parse_ndjson_sends_nodes_and_relations() {
+        let ndjson = r#"{"kind": "node", "name": "a", "type": "t"}
+{"kind": "relation", "type": "r", "source": ["a"], "target": ["b"], "op": "removed"}
+{"kind": "node", "name": "b", "type": "t", "parent": ["a"], "op": "updated"}"#;
+        let (nodes, relations) = collect(|tx| parse_ndjson(ndjson.as_bytes(), tx));
+        assert_eq!(nodes, vec![node("a", &[], Op::Added), node("b", &["a"], Op::Updated)]);
+        assert_eq!(relations.len(), 1);
+        assert_eq!(relations[0].source, vec!["a"]);
+        assert_eq!(relations[0].op, Op::Removed);
+    }
+
+    #[test]
+    fn parse_ndjson_fails_on_an_invalid_line() {
+        let (tx, _rx) = mpsc::channel(16);
+        let ndjson = "{\"kind\": \"node\", \"name\": \"a\", \"type\": \"t\"}\n{\"kind\": \"other\"}";
+        assert!(parse_ndjson(ndjson.as_bytes(), tx).is_err());
+    }
+}
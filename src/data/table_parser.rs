@@ -0,0 +1,370 @@
+// Parsers for the nodes and edges tables
+use crate::data::source_model::{ColumnMapping, NodeRecord, Op, Relation, TableKind, Tag};
+use crate::data::source_parser::SourceItem;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use std::error::Error;
+use std::io::{self, Read};
+use tokio::sync::mpsc::Sender;
+
+type ParseError = Box<dyn Error + Sync + Send>;
+
+/// Parses a CSV table with headers sending a node or relation per row.
+/// Blocks on the channel, call it from a blocking task.
+pub fn parse_csv<R: Read>(
+    kind: TableKind,
+    columns: &ColumnMapping,
+    reader: R,
+    tx: Sender<SourceItem>,
+) -> Result<(), ParseError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_owned()).collect();
+    let mapper = TableMapper::new(kind, columns, &headers)?;
+
+    for record in reader.records() {
+        let record = record?;
+        let values: Vec<Option<&str>> = record
+            .iter()
+            .map(|v| if v.is_empty() { None } else { Some(v) })
+            .collect();
+        send(&tx, mapper.item(&values)?)?;
+    }
+    Ok(())
+}
+
+/// Parses a Parquet table sending a node or relation per row.
+/// Parquet files need random access, the file is spooled to a temporary file first and its
+/// row groups are read from there, so it is never in memory as a whole.
+/// Blocks on the channel, call it from a blocking task.
+pub fn parse_parquet<R: Read>(
+    kind: TableKind,
+    columns: &ColumnMapping,
+    mut reader: R,
+    tx: Sender<SourceItem>,
+) -> Result<(), ParseError> {
+    // removed by the OS once it is closed
+    let mut spool = tempfile::tempfile()?;
+    io::copy(&mut reader, &mut spool)?;
+    let file = SerializedFileReader::new(spool)?;
+
+    let headers: Vec<String> = file
+        .metadata()
+        .file_metadata()
+        .schema()
+        .get_fields()
+        .iter()
+        .map(|f| f.name().to_owned())
+        .collect();
+    let mapper = TableMapper::new(kind, columns, &headers)?;
+
+    for row in file.get_row_iter(None)? {
+        let row = row?;
+        let values: Vec<Option<String>> = row
+            .get_column_iter()
+            .map(|(_, field)| match field {
+                Field::Null => None,
+                Field::Str(s) => Some(s.clone()),
+                f => Some(f.to_string()),
+            })
+            .collect();
+        let values: Vec<Option<&str>> = values.iter().map(|v| v.as_deref()).collect();
+        send(&tx, mapper.item(&values)?)?;
+    }
+    Ok(())
+}
+
+fn send(tx: &Sender<SourceItem>, item: SourceItem) -> Result<(), ParseError> {
+    tx.blocking_send(item)
+        .map_err(|_| "item receiver closed".into())
+}
+
+/// Maps the values of a row to a node or relation using the position of the mapped columns.
+struct TableMapper {
+    kind: TableKind,
+    separator: String,
+    path: usize,
+    name: Option<usize>,
+    source: usize,
+    target: usize,
+    type_field: usize,
+    tags: Vec<(String, usize)>,
+    op: Option<usize>,
+}
+
+impl TableMapper {
+    fn new(kind: TableKind, columns: &ColumnMapping, headers: &[String]) -> Result<Self, ParseError> {
+        let position = |column: &str| -> Result<usize, ParseError> {
+            headers
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| format!("Column {} not found in {:?}", column, headers).into())
+        };
+        let positions = |columns: &[String]| -> Result<Vec<(String, usize)>, ParseError> {
+            columns
+                .iter()
+                .map(|c| Ok((c.clone(), position(c)?)))
+                .collect()
+        };
+
+        let mapper = match kind {
+            TableKind::Nodes => TableMapper {
+                kind,
+                separator: columns.path_separator.clone(),
+                path: position(&columns.path)?,
+                name: columns.name.as_deref().map(position).transpose()?,
+                source: 0,
+                target: 0,
+                type_field: position(&columns.type_field)?,
+                tags: positions(&columns.tags)?,
+                op: columns.op.as_deref().map(position).transpose()?,
+            },
+            TableKind::Edges => TableMapper {
+                kind,
+                separator: columns.path_separator.clone(),
+                path: 0,
+                name: None,
+                source: position(&columns.source)?,
+                target: position(&columns.target)?,
+                type_field: position(&columns.relation_type)?,
+                tags: positions(&columns.relation_tags)?,
+                op: columns.op.as_deref().map(position).transpose()?,
+            },
+        };
+        Ok(mapper)
+    }
+
+    fn item(&self, values: &[Option<&str>]) -> Result<SourceItem, ParseError> {
+        let tags: Vec<Tag> = self
+            .tags
+            .iter()
+            .filter_map(|(column, i)| {
+                values.get(*i).copied().flatten().map(|v| Tag {
+                    type_field: column.clone(),
+                    value: v.to_owned(),
+                })
+            })
+            .collect();
+        let tags = if tags.is_empty() { None } else { Some(tags) };
+        let type_field = self.value(values, self.type_field)?.to_owned();
+        let op = match self.op.and_then(|i| values.get(i).copied().flatten()) {
+            Some(v) => Op::parse(v).ok_or_else(|| format!("Invalid op {} in row {:?}", v, values))?,
+            None => Op::default(),
+        };
+
+        match self.kind {
+            TableKind::Nodes => {
+                let mut parent = self.split(values.get(self.path).copied().flatten());
+                let name = match self.name {
+                    Some(i) => self.value(values, i)?.to_owned(),
+                    None => parent.pop().ok_or("Empty node path")?,
+                };
+                Ok(SourceItem::NodeRecord(NodeRecord {
+                    name,
+                    type_field,
+                    parent,
+                    tags,
+                    op,
+                }))
+            }
+            TableKind::Edges => {
+                let source = self.split(Some(self.value(values, self.source)?));
+                let target = self.split(Some(self.value(values, self.target)?));
+                if source.is_empty() || target.is_empty() {
+                    return Err(format!("Empty relation path in row {:?}", values).into());
+                }
+                Ok(SourceItem::Relation(Relation {
+                    type_field,
+                    source,
+                    target,
+                    tags,
+                    op,
+                }))
+            }
+        }
+    }
+
+    fn value<'a>(&self, values: &[Option<&'a str>], i: usize) -> Result<&'a str, ParseError> {
+        values
+            .get(i)
+            .copied()
+            .flatten()
+            .ok_or_else(|| format!("Missing value in column {} of row {:?}", i, values).into())
+    }
+
+    fn split(&self, path: Option<&str>) -> Vec<String> {
+        path.unwrap_or_default()
+            .split(self.separator.as_str())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_owned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tokio::sync::mpsc;
+
+    type Parser = fn(TableKind, &ColumnMapping, File, Sender<SourceItem>) -> Result<(), ParseError>;
+
+    fn columns() -> ColumnMapping {
+        ColumnMapping {
+            tags: vec!["tag_type_1".to_owned(), "tag_type_a".to_owned(), "tag_type_b".to_owned()],
+            relation_tags: vec!["tag_type_x".to_owned(), "tag_type_y".to_owned()],
+            ..Default::default()
+        }
+    }
+
+    fn parse_file(parser: Parser, kind: TableKind, path: &str, columns: &ColumnMapping) -> Vec<SourceItem> {
+        let (tx, mut rx) = mpsc::channel(1024);
+        parser(kind, columns, File::open(path).unwrap(), tx).unwrap();
+        let mut items = vec![];
+        while let Ok(item) = rx.try_recv() {
+            items.push(item);
+        }
+        items
+    }
+
+    fn parse_str(kind: TableKind, csv: &str, columns: &ColumnMapping) -> Result<Vec<SourceItem>, ParseError> {
+        let (tx, mut rx) = mpsc::channel(1024);
+        parse_csv(kind, columns, csv.as_bytes(), tx)?;
+        let mut items = vec![];
+        while let Ok(item) = rx.try_recv() {
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    fn nodes(items: Vec<SourceItem>) -> Vec<NodeRecord> {
+        items
+            .into_iter()
+            .map(|item| match item {
+                SourceItem::NodeRecord(node) => node,
+                other => panic!("expected a node, got {:?}", other),
+            })
+            .collect()
+    }
+
+    fn relations(items: Vec<SourceItem>) -> Vec<Relation> {
+        items
+            .into_iter()
+            .map(|item| match item {
+                SourceItem::Relation(relation) => relation,
+                other => panic!("expected a relation, got {:?}", other),
+            })
+            .collect()
+    }
+
+    fn tag(type_field: &str, value: &str) -> Tag {
+        Tag {
+            type_field: type_field.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    fn path(path: &[&str]) -> Vec<String> {
+        path.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn check_nodes(parser: Parser, file: &str) {
+        let nodes = nodes(parse_file(parser, TableKind::Nodes, file, &columns()));
+        assert_eq!(nodes.len(), 18);
+
+        assert_eq!(nodes[0].name, "root");
+        assert_eq!(nodes[0].type_field, "root_type");
+        assert!(nodes[0].parent.is_empty());
+        assert_eq!(nodes[0].tags, None);
+        assert_eq!(nodes[0].op, Op::Added);
+
+        let node = &nodes[3];
+        assert_eq!(node.name, "children111");
+        assert_eq!(node.parent, path(&["root", "children1", "children11"]));
+        assert_eq!(node.tags, Some(vec![tag("tag_type_a", "value_a"), tag("tag_type_b", "value_b")]));
+
+        // only the mapped columns are tags, the empty ones are skipped
+        let node = &nodes[5];
+        assert_eq!(node.name, "children1112");
+        assert_eq!(node.type_field, "type111");
+        assert_eq!(node.tags, Some(vec![tag("tag_type_1", "value_1")]));
+        assert_eq!(nodes[7].tags, None);
+    }
+
+    fn check_edges(parser: Parser, file: &str) {
+        let relations = relations(parse_file(parser, TableKind::Edges, file, &columns()));
+        assert_eq!(relations.len(), 3);
+
+        assert_eq!(relations[0].type_field, "rel_type1");
+        assert_eq!(relations[0].source, path(&["children1"]));
+        assert_eq!(relations[0].target, path(&["children2"]));
+        assert_eq!(relations[0].tags, None);
+        assert_eq!(relations[1].type_field, "rel_type2");
+
+        assert_eq!(relations[2].source, path(&["children1", "children12"]));
+        assert_eq!(relations[2].target, path(&["children2", "children13"]));
+        assert_eq!(
+            relations[2].tags,
+            Some(vec![tag("tag_type_x", "value_x"), tag("tag_type_y", "value_y")])
+        );
+    }
+
+    #[test]
+    fn parse_csv_nodes() {
+        check_nodes(parse_csv, "data/nodes_example.csv");
+    }
+
+    #[test]
+    fn parse_csv_edges() {
+        check_edges(parse_csv, "data/edges_example.csv");
+    }
+
+    #[test]
+    fn parse_parquet_nodes() {
+        check_nodes(parse_parquet, "data/nodes_example.parquet");
+    }
+
+    #[test]
+    fn parse_parquet_edges() {
+        check_edges(parse_parquet, "data/edges_example.parquet");
+    }
+
+    #[test]
+    fn parse_csv_with_name_separator_and_op() {
+        let columns = ColumnMapping {
+            path: "parent".to_owned(),
+            path_separator: ".".to_owned(),
+            name: Some("name".to_owned()),
+            op: Some("op".to_owned()),
+            ..Default::default()
+        };
+        let csv = "parent,name,type,op\n,a,t,\na.b,c,t,Removed\n";
+        let nodes = nodes(parse_str(TableKind::Nodes, csv, &columns).unwrap());
+
+        assert_eq!(nodes[0].name, "a");
+        assert!(nodes[0].parent.is_empty());
+        assert_eq!(nodes[0].op, Op::Added);
+        assert_eq!(nodes[1].name, "c");
+        assert_eq!(nodes[1].parent, path(&["a", "b"]));
+        assert_eq!(nodes[1].op, Op::Removed);
+    }
+
+    #[test]
+    fn parse_csv_errors() {
+        let columns = ColumnMapping::default();
+        let error = parse_str(TableKind::Nodes, "name,type\na,t\n", &columns).unwrap_err();
+        assert!(error.to_string().contains("Column path not found"));
+
+        let error = parse_str(TableKind::Nodes, "path,type\na,\n", &columns).unwrap_err();
+        assert!(error.to_string().contains("Missing value"));
+
+        let error = parse_str(TableKind::Edges, "source,target,type\na,/,r\n", &columns).unwrap_err();
+        assert!(error.to_string().contains("Empty relation path"));
+
+        let columns = ColumnMapping {
+            op: Some("op".to_owned()),
+            ..Default::default()
+        };
+        let error = parse_str(TableKind::Nodes, "path,type,op\na,t,moved\n", &columns).unwrap_err();
+        assert!(error.to_string().contains("Invalid op"));
+    }
+}
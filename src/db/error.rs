@@ -0,0 +1,52 @@
+use crate::db::model::SaveReport;
+use scylla::transport::errors::{DbError, QueryError};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SaveNodesError {
+    /// Too many rows or index rows could not be written, carries the report with the failed rows
+    FailureThreshold(SaveReport),
+}
+
+impl fmt::Display for SaveNodesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveNodesError::FailureThreshold(report) => write!(
+                f,
+                "{} of {} rows and {} of {} index rows failed to be persisted",
+                report.failed, report.rows, report.index_failed, report.index_rows
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveNodesError {}
+
+/// Errors of a query rejected by ScyllaDB as invalid, like a query with a tampered paging state.
+pub fn is_invalid_query(error: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<QueryError>(),
+        Some(QueryError::DbError(DbError::Invalid | DbError::ProtocolError, _))
+    )
+}
+
+/// Errors worth retrying, the query may succeed if it is sent again.
+pub fn is_transient(error: &QueryError) -> bool {
+    match error {
+        QueryError::IoError(_)
+        | QueryError::TimeoutError
+        | QueryError::RequestTimeout(_)
+        | QueryError::TooManyOrphanedStreamIds(_)
+        | QueryError::UnableToAllocStreamId => true,
+        QueryError::DbError(e, _) => matches!(
+            e,
+            DbError::Unavailable { .. }
+                | DbError::Overloaded
+                | DbError::IsBootstrapping
+                | DbError::ReadTimeout { .. }
+                | DbError::WriteTimeout { .. }
+                | DbError::RateLimitReached { .. }
+        ),
+        _ => false,
+    }
+}
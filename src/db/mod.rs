@@ -1,2 +1,3 @@
 pub mod scylladb;
-pub mod model;
\ No newline at end of file
+pub mod model;
+pub mod error;
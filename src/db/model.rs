@@ -1,7 +1,10 @@
 use scylla::macros::FromRow;
+use serde::Serialize;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::{data::{source_model::Tag, model::{get_id_from_url, Relation}}, DIR};
+use crate::search::prefix::prefix_keys;
 
 #[derive(Default, Debug, Clone, FromRow)]
 pub struct DbNode {
@@ -25,6 +28,26 @@ pub struct DbNodeSimple {
     pub ingestion_id: String,
 }
 
+/// Primary key of a row of `graph.nodes`
+#[derive(Debug, Clone)]
+pub struct DbRowKey {
+    pub uuid: Uuid,
+    pub direction: String,
+    pub relation: String,
+    pub relates_to: String,
+}
+
+impl DbRowKey {
+    pub fn from(node: &DbNode) -> Self {
+        Self {
+            uuid: node.uuid,
+            direction: node.direction.clone().unwrap_or_default(),
+            relation: node.relation.clone().unwrap_or_default(),
+            relates_to: node.relates_to.clone().unwrap_or_default(),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, FromRow)]
 pub struct DbRelation {
     pub uuid: Uuid,
@@ -33,9 +56,96 @@ pub struct DbRelation {
     pub relates_to: Option<String>,
     pub name: String,
     pub node_type: String,
+    pub tags: Option<Vec<(String, String)>>,
+}
+
+/// Row of `graph.nodes_by_tag`, the index of the nodes by tag
+#[derive(Debug, Clone)]
+pub struct DbTagRow {
+    pub ingestion_id: String,
+    pub tag_type: String,
+    pub tag_value: String,
+    pub node_type: String,
+    pub uuid: Uuid,
+    pub name: String,
+    pub url: String,
+}
+
+impl DbTagRow {
+    /// Index rows of the tags of a node, only the root row of the node has them.
+    pub fn from(node: &DbNode) -> Vec<Self> {
+        if !node.is_root() {
+            return vec![];
+        }
+        node.tags
+            .iter()
+            .flatten()
+            .map(|(tag_type, tag_value)| Self {
+                ingestion_id: node.ingestion_id.clone(),
+                tag_type: tag_type.clone(),
+                tag_value: tag_value.clone(),
+                node_type: node.node_type.clone(),
+                uuid: node.uuid,
+                name: node.name.clone(),
+                url: node.url.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Row of `graph.nodes_by_prefix`, the index of the nodes by prefixes of their name and URL
+#[derive(Debug, Clone)]
+pub struct DbPrefixRow {
+    pub ingestion_id: String,
+    pub prefix: String,
+    pub exact: bool,
+    pub depth: i32,
+    pub uuid: Uuid,
+    pub name: String,
+    pub url: String,
+    pub node_type: String,
+}
+
+impl DbPrefixRow {
+    /// Index rows of the prefixes of a node, only the root row of the node has its name and URL.
+    pub fn from(node: &DbNode) -> Vec<Self> {
+        if !node.is_root() {
+            return vec![];
+        }
+        let depth = node.url.matches('/').count() as i32;
+        prefix_keys(&node.name, &node.url)
+            .into_iter()
+            .map(|(prefix, exact)| Self {
+                ingestion_id: node.ingestion_id.clone(),
+                prefix,
+                exact,
+                depth,
+                uuid: node.uuid,
+                name: node.name.clone(),
+                url: node.url.clone(),
+                node_type: node.node_type.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Row of `graph.node_stats`, `types` counts the descendants by node type
+#[derive(Default, Debug, Clone, FromRow)]
+pub struct DbNodeStats {
+    pub uuid: Uuid,
+    pub ingestion_id: String,
+    pub children: i64,
+    pub descendants: i64,
+    pub depth: i32,
+    pub types: Option<HashMap<String, i64>>,
 }
 
 impl DbNode {
+    /// The row with the name, type and tags of the node, the other rows are its relations
+    pub fn is_root(&self) -> bool {
+        self.direction.as_deref().unwrap_or_default().is_empty()
+    }
+
     pub fn root(
         ingestion_id: String,
         url: String,
@@ -104,10 +214,120 @@ impl DbNode {
             relation: Some(relation.rel_type.to_owned()),
             relates_to: Some(relation.relates_to.to_owned()),
             name: relation.target_name.to_owned(),
-            ingestion_id: ingestion_id,
+            ingestion_id,
             url: "".to_owned(),
             node_type: "".to_owned(),
-            tags: None,
+            tags: Some(relation.tags.to_owned()),
+        }
+    }
+}
+
+pub const MAX_REPORTED_FAILED_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRow {
+    pub uuid: Uuid,
+    pub direction: String,
+    pub relation: String,
+    pub relates_to: String,
+    pub name: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct SaveReport {
+    pub rows: usize,
+    pub written: usize,
+    pub failed: usize,
+    pub retries: usize,
+    /// Failed rows, capped to avoid huge reports. `failed` has the total count.
+    pub failed_rows: Vec<FailedRow>,
+    /// Rows of the type, tag and prefix indexes
+    pub index_rows: usize,
+    pub index_failed: usize,
+    /// Errors of the failed index rows, capped like `failed_rows`
+    pub index_errors: Vec<String>,
+}
+
+impl SaveReport {
+    pub fn merge(&mut self, other: SaveReport) {
+        self.rows += other.rows;
+        self.written += other.written;
+        self.failed += other.failed;
+        self.retries += other.retries;
+        let free = MAX_REPORTED_FAILED_ROWS.saturating_sub(self.failed_rows.len());
+        self.failed_rows.extend(other.failed_rows.into_iter().take(free));
+        self.index_rows += other.index_rows;
+        self.index_failed += other.index_failed;
+        let free = MAX_REPORTED_FAILED_ROWS.saturating_sub(self.index_errors.len());
+        self.index_errors.extend(other.index_errors.into_iter().take(free));
+    }
+
+    pub fn fail(&mut self, mut row: FailedRow, attempts: u32, error: String) {
+        self.failed += 1;
+        if self.failed_rows.len() < MAX_REPORTED_FAILED_ROWS {
+            row.attempts = attempts;
+            row.error = error;
+            self.failed_rows.push(row);
+        }
+    }
+
+    pub fn fail_index(&mut self, error: String) {
+        self.index_failed += 1;
+        if self.index_errors.len() < MAX_REPORTED_FAILED_ROWS {
+            self.index_errors.push(error);
+        }
+    }
+
+    /// True if the ratio of failed rows, or of failed index rows, is above the threshold.
+    pub fn exceeds(&self, failure_threshold: f64) -> bool {
+        let exceeds = |failed: usize, rows: usize| {
+            failed > 0 && failed as f64 > rows as f64 * failure_threshold
+        };
+        exceeds(self.failed, self.rows) || exceeds(self.index_failed, self.index_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(rows: usize, failed: usize, index_rows: usize, index_failed: usize) -> SaveReport {
+        SaveReport {
+            rows,
+            written: rows - failed,
+            failed,
+            index_rows,
+            index_failed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exceeds_checks_rows_and_index_rows_apart() {
+        assert!(!report(100, 0, 1000, 0).exceeds(0.0));
+        assert!(report(100, 1, 1000, 0).exceeds(0.0));
+        assert!(report(100, 0, 1000, 1).exceeds(0.0));
+
+        assert!(!report(100, 10, 1000, 100).exceeds(0.1));
+        assert!(report(100, 11, 1000, 0).exceeds(0.1));
+        // the index rows don't dilute the failed rows
+        assert!(report(100, 0, 1000, 101).exceeds(0.1));
+    }
+
+    #[test]
+    fn merge_caps_the_reported_errors() {
+        let mut total = SaveReport::default();
+        for _ in 0..2 {
+            let mut other = report(MAX_REPORTED_FAILED_ROWS, 0, MAX_REPORTED_FAILED_ROWS, 0);
+            for _ in 0..MAX_REPORTED_FAILED_ROWS {
+                other.fail_index("error".to_owned());
+            }
+            total.merge(other);
         }
+        assert_eq!(total.index_rows, 2 * MAX_REPORTED_FAILED_ROWS);
+        assert_eq!(total.index_failed, 2 * MAX_REPORTED_FAILED_ROWS);
+        assert_eq!(total.index_errors.len(), MAX_REPORTED_FAILED_ROWS);
     }
 }
@@ -1,10 +1,20 @@
-use crate::db::model::{DbNode, DbNodeSimple, DbRelation};
+use crate::db::error::is_transient;
+use crate::db::model::{
+    DbNode, DbNodeSimple, DbNodeStats, DbPrefixRow, DbRelation, DbRowKey, DbTagRow, FailedRow,
+    SaveReport,
+};
 
 use scylla::prepared_statement::PreparedStatement;
+use scylla::query::Query;
+use scylla::frame::value::ValueList;
 use scylla::statement::Consistency;
 use scylla::transport::load_balancing::{DcAwareRoundRobinPolicy, TokenAwarePolicy};
 use scylla::transport::Compression;
+use scylla::QueryResult;
 use scylla::{Session, SessionBuilder};
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Semaphore;
@@ -14,24 +24,104 @@ use uuid::Uuid;
 use std::time::Duration;
 use std::fs;
 
+/// Attempts and result of a write, the values are returned with the error
+type WriteResult<V> = (u32, Result<(), (V, String)>);
+
+/// Retries for transient write errors.
+#[derive(Debug, Clone, Copy)]
+pub struct WritePolicy {
+    pub retries: u32,
+    pub backoff_ms: u64,
+}
+
 pub struct ScyllaDbService {
     parallelism: usize,
+    write_policy: WritePolicy,
     db_session: Arc<Session>,
     ps: Arc<PreparedStatement>,
     ps_traversal: Arc<PreparedStatement>,
     ps_traversal_relation: Arc<PreparedStatement>,
+    ps_manifest: Arc<PreparedStatement>,
+    ps_manifest_page: Arc<PreparedStatement>,
+    ps_delete: Arc<PreparedStatement>,
+    ps_delete_row: Arc<PreparedStatement>,
+    ps_relations: Arc<PreparedStatement>,
+    ps_relations_direction: Arc<PreparedStatement>,
+    ps_relations_direction_relation: Arc<PreparedStatement>,
+    ps_relations_relation: Arc<PreparedStatement>,
+    ps_stats: Arc<PreparedStatement>,
+    ps_delete_stats: Arc<PreparedStatement>,
+    ps_tag: Arc<PreparedStatement>,
+    ps_delete_tag: Arc<PreparedStatement>,
+    ps_search_tag: Arc<PreparedStatement>,
+    ps_search_tag_type: Arc<PreparedStatement>,
+    ps_type: Arc<PreparedStatement>,
+    ps_delete_type: Arc<PreparedStatement>,
+    ps_type_nodes: Arc<PreparedStatement>,
+    ps_type_count: Arc<PreparedStatement>,
+    ps_delete_type_count: Arc<PreparedStatement>,
+    ps_prefix: Arc<PreparedStatement>,
+    ps_delete_prefix: Arc<PreparedStatement>,
+    ps_search_prefix: Arc<PreparedStatement>,
 }
 
+/// Ids of the nodes of an ingestion read from the manifest per page
+const MANIFEST_PAGE_SIZE: i32 = 1000;
+
 const INSERT_QUERY: &str = "INSERT INTO graph.nodes (id, direction, relation, relates_to, name, ingestion_id, url, item_type, tags) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
 const GET_ONE_QUERY: &str =
     "SELECT id, name, item_type, url, ingestion_id FROM graph.nodes WHERE id = ? and direction = '' and relation = ''";
 const GET_ONE_QUERY_TAGS: &str = "SELECT id, direction, relation, relates_to, name, ingestion_id, url, item_type, tags FROM graph.nodes WHERE id = ? and direction = '' and relation = ''";
 const GET_ONE_QUERY_RELATIONS: &str = "SELECT id, direction, relation, relates_to, name, ingestion_id, url, item_type, tags FROM graph.nodes WHERE id = ?";
-const GET_ONE_QUERY_DIRECTION: &str = "SELECT id, direction, relation, relates_to, name, item_type FROM graph.nodes WHERE id = ? and direction in ('',?)";
-const GET_ONE_QUERY_DIRECTION_RELATION: &str = "SELECT id, direction, relation, relates_to, name, item_type FROM graph.nodes WHERE id = ? and direction in ('',?) and relation in ('',?)";
+const GET_RELATIONS_QUERY: &str = "SELECT id, direction, relation, relates_to, name, item_type, tags FROM graph.nodes WHERE id = ? and direction > ''";
+const GET_RELATIONS_QUERY_DIRECTION: &str = "SELECT id, direction, relation, relates_to, name, item_type, tags FROM graph.nodes WHERE id = ? and direction = ?";
+const GET_RELATIONS_QUERY_DIRECTION_RELATION: &str = "SELECT id, direction, relation, relates_to, name, item_type, tags FROM graph.nodes WHERE id = ? and direction = ? and relation = ?";
+const GET_RELATIONS_QUERY_RELATION: &str = "SELECT id, direction, relation, relates_to, name, item_type, tags FROM graph.nodes WHERE id = ? and direction in ('IN','OUT') and relation = ?";
+const GET_ONE_QUERY_DIRECTION: &str = "SELECT id, direction, relation, relates_to, name, item_type, tags FROM graph.nodes WHERE id = ? and direction in ('',?)";
+const INSERT_MANIFEST_QUERY: &str = "INSERT INTO graph.ingestion_nodes (ingestion_id, id) VALUES (?, ?)";
+const GET_MANIFEST_QUERY: &str = "SELECT id FROM graph.ingestion_nodes WHERE ingestion_id = ?";
+const HAS_MANIFEST_QUERY: &str = "SELECT id FROM graph.ingestion_nodes WHERE ingestion_id = ? LIMIT 1";
+const DELETE_MANIFEST_QUERY: &str = "DELETE FROM graph.ingestion_nodes WHERE ingestion_id = ?";
+const DELETE_NODE_QUERY: &str = "DELETE FROM graph.nodes WHERE id = ?";
+const DELETE_ROW_QUERY: &str = "DELETE FROM graph.nodes WHERE id = ? and direction = ? and relation = ? and relates_to = ?";
+const INSERT_JOB_QUERY: &str = "INSERT INTO graph.jobs (id, ingestion_id, kind, status, job) VALUES (?, ?, ?, ?, ?) USING TTL ?";
+const GET_INGESTION_JOBS_QUERY: &str = "SELECT id, kind, status FROM graph.jobs WHERE ingestion_id = ?";
+const GET_JOB_QUERY: &str = "SELECT job FROM graph.jobs WHERE id = ?";
+const CANCEL_JOB_QUERY: &str = "UPDATE graph.jobs USING TTL ? SET cancel_requested = true WHERE id = ? IF EXISTS";
+const GET_CANCEL_REQUESTS_QUERY: &str = "SELECT id, cancel_requested FROM graph.jobs WHERE id IN ?";
+const GET_ALIAS_QUERY: &str = "SELECT ingestion_id FROM graph.aliases WHERE alias = ?";
+const INSERT_ALIAS_QUERY: &str = "INSERT INTO graph.aliases (alias, ingestion_id, updated_at) VALUES (?, ?, toTimestamp(now())) IF NOT EXISTS";
+const UPDATE_ALIAS_QUERY: &str = "UPDATE graph.aliases SET ingestion_id = ?, updated_at = toTimestamp(now()) WHERE alias = ? IF ingestion_id = ?";
+const CLAIM_VERSION_QUERY: &str = "INSERT INTO graph.versions (ingestion_id, alias, created_at) VALUES (?, ?, toTimestamp(now())) IF NOT EXISTS";
+const GET_VERSION_ALIAS_QUERY: &str = "SELECT alias FROM graph.versions WHERE ingestion_id = ?";
+const DELETE_VERSION_QUERY: &str = "DELETE FROM graph.versions WHERE ingestion_id = ?";
+const INSERT_STATS_QUERY: &str = "INSERT INTO graph.node_stats (id, ingestion_id, children, descendants, depth, types) VALUES (?, ?, ?, ?, ?, ?)";
+const GET_STATS_QUERY: &str = "SELECT id, ingestion_id, children, descendants, depth, types FROM graph.node_stats WHERE id = ?";
+const DELETE_STATS_QUERY: &str = "DELETE FROM graph.node_stats WHERE id = ?";
+const INSERT_TAG_QUERY: &str = "INSERT INTO graph.nodes_by_tag (ingestion_id, tag_type, tag_value, item_type, id, name, url) VALUES (?, ?, ?, ?, ?, ?, ?)";
+const DELETE_TAG_QUERY: &str = "DELETE FROM graph.nodes_by_tag WHERE ingestion_id = ? and tag_type = ? and tag_value = ? and item_type = ? and id = ?";
+const SEARCH_TAG_QUERY: &str = "SELECT id, name, item_type, url, ingestion_id FROM graph.nodes_by_tag WHERE ingestion_id = ? and tag_type = ? and tag_value = ?";
+const SEARCH_TAG_QUERY_TYPE: &str = "SELECT id, name, item_type, url, ingestion_id FROM graph.nodes_by_tag WHERE ingestion_id = ? and tag_type = ? and tag_value = ? and item_type = ?";
+const INSERT_TYPE_QUERY: &str = "INSERT INTO graph.nodes_by_type (ingestion_id, item_type, id, name, url) VALUES (?, ?, ?, ?, ?)";
+const DELETE_TYPE_QUERY: &str = "DELETE FROM graph.nodes_by_type WHERE ingestion_id = ? and item_type = ? and id = ?";
+const GET_TYPE_NODES_QUERY: &str = "SELECT id, name, item_type, url, ingestion_id FROM graph.nodes_by_type WHERE ingestion_id = ? and item_type = ?";
+const INSERT_TYPE_COUNT_QUERY: &str = "INSERT INTO graph.ingestion_types (ingestion_id, item_type, nodes) VALUES (?, ?, ?)";
+const GET_TYPE_COUNTS_QUERY: &str = "SELECT item_type, nodes FROM graph.ingestion_types WHERE ingestion_id = ?";
+const DELETE_TYPE_COUNT_QUERY: &str = "DELETE FROM graph.ingestion_types WHERE ingestion_id = ? and item_type = ?";
+const DELETE_TYPE_COUNTS_QUERY: &str = "DELETE FROM graph.ingestion_types WHERE ingestion_id = ?";
+const INSERT_PREFIX_QUERY: &str = "INSERT INTO graph.nodes_by_prefix (ingestion_id, prefix, exact, depth, id, name, url, item_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+const DELETE_PREFIX_QUERY: &str = "DELETE FROM graph.nodes_by_prefix WHERE ingestion_id = ? and prefix = ? and exact = ? and depth = ? and id = ?";
+const SEARCH_PREFIX_QUERY: &str = "SELECT id, name, item_type, url, ingestion_id FROM graph.nodes_by_prefix WHERE ingestion_id = ? and prefix = ? LIMIT ?";
+const GET_ONE_QUERY_DIRECTION_RELATION: &str = "SELECT id, direction, relation, relates_to, name, item_type, tags FROM graph.nodes WHERE id = ? and direction in ('',?) and relation in ('',?)";
 
 impl ScyllaDbService {
-    pub async fn new(dc: String, host: String, db_parallelism: usize, schema_file: String) -> Self {
+    pub async fn new(
+        dc: String,
+        host: String,
+        db_parallelism: usize,
+        write_policy: WritePolicy,
+        schema_file: String,
+    ) -> Self {
         debug!("ScyllaDbService: Connecting to {}. DC: {}.", host, dc);
 
         let dc_robin = Box::new(DcAwareRoundRobinPolicy::new(dc.to_string()));
@@ -50,7 +140,7 @@ impl ScyllaDbService {
         info!("ScyllaDbService: Creating Schema..");
       
         let schema = fs::read_to_string(&schema_file)
-        .expect(("Error Reading Schema File".to_owned() + schema_file.as_str()).as_str());
+        .unwrap_or_else(|_| panic!("Error Reading Schema File {}", schema_file));
 
         let schema_query = schema.trim().replace("\n", "");
 
@@ -88,9 +178,105 @@ impl ScyllaDbService {
             .await
             .expect("Error Creating Prepared Query");
         ps_tr.set_consistency(Consistency::One);
+        let mut ps_m = session
+            .prepare(INSERT_MANIFEST_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        ps_m.set_consistency(Consistency::Any);
+        let mut ps_mp = session
+            .prepare(GET_MANIFEST_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        ps_mp.set_page_size(MANIFEST_PAGE_SIZE);
+        let ps_d = session
+            .prepare(DELETE_NODE_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_dr = session
+            .prepare(DELETE_ROW_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_r = session
+            .prepare(GET_RELATIONS_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_rd = session
+            .prepare(GET_RELATIONS_QUERY_DIRECTION)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_rdr = session
+            .prepare(GET_RELATIONS_QUERY_DIRECTION_RELATION)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_rr = session
+            .prepare(GET_RELATIONS_QUERY_RELATION)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_s = session
+            .prepare(INSERT_STATS_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_ds = session
+            .prepare(DELETE_STATS_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let mut ps_tag = session
+            .prepare(INSERT_TAG_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        ps_tag.set_consistency(Consistency::Any);
+        let ps_dt = session
+            .prepare(DELETE_TAG_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_st = session
+            .prepare(SEARCH_TAG_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_stt = session
+            .prepare(SEARCH_TAG_QUERY_TYPE)
+            .await
+            .expect("Error Creating Prepared Query");
+        let mut ps_type = session
+            .prepare(INSERT_TYPE_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        ps_type.set_consistency(Consistency::Any);
+        let ps_dty = session
+            .prepare(DELETE_TYPE_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_tn = session
+            .prepare(GET_TYPE_NODES_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_tc = session
+            .prepare(INSERT_TYPE_COUNT_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_dtc = session
+            .prepare(DELETE_TYPE_COUNT_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let mut ps_prefix = session
+            .prepare(INSERT_PREFIX_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        ps_prefix.set_consistency(Consistency::Any);
+        let ps_dp = session
+            .prepare(DELETE_PREFIX_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
+        let ps_sp = session
+            .prepare(SEARCH_PREFIX_QUERY)
+            .await
+            .expect("Error Creating Prepared Query");
         
         let db_session = Arc::new(session);
-        info!("ScyllaDbService: Parallelism {}", db_parallelism);
+        info!(
+            "ScyllaDbService: Parallelism {}. Write Policy {:?}",
+            db_parallelism, write_policy
+        );
 
         let prepared_query = Arc::new(ps);    
         let ps_traversal = Arc::new(ps_t);    
@@ -99,9 +285,32 @@ impl ScyllaDbService {
         ScyllaDbService {
             db_session,
             parallelism: db_parallelism,
+            write_policy,
             ps: prepared_query,
             ps_traversal,
             ps_traversal_relation,
+            ps_manifest: Arc::new(ps_m),
+            ps_manifest_page: Arc::new(ps_mp),
+            ps_delete: Arc::new(ps_d),
+            ps_delete_row: Arc::new(ps_dr),
+            ps_relations: Arc::new(ps_r),
+            ps_relations_direction: Arc::new(ps_rd),
+            ps_relations_direction_relation: Arc::new(ps_rdr),
+            ps_relations_relation: Arc::new(ps_rr),
+            ps_stats: Arc::new(ps_s),
+            ps_delete_stats: Arc::new(ps_ds),
+            ps_tag: Arc::new(ps_tag),
+            ps_delete_tag: Arc::new(ps_dt),
+            ps_search_tag: Arc::new(ps_st),
+            ps_search_tag_type: Arc::new(ps_stt),
+            ps_type: Arc::new(ps_type),
+            ps_delete_type: Arc::new(ps_dty),
+            ps_type_nodes: Arc::new(ps_tn),
+            ps_type_count: Arc::new(ps_tc),
+            ps_delete_type_count: Arc::new(ps_dtc),
+            ps_prefix: Arc::new(ps_prefix),
+            ps_delete_prefix: Arc::new(ps_dp),
+            ps_search_prefix: Arc::new(ps_sp),
         }
     }
 
@@ -114,6 +323,95 @@ impl ScyllaDbService {
         return self.get_node_int(id, tags, relations).await;
     }
 
+    /// Gets many nodes concurrently, up to `parallelism` at a time. The result of each id is
+    /// kept so a failed lookup does not fail the others.
+    pub async fn get_nodes(
+        &self,
+        ids: Vec<String>,
+        tags: bool,
+        relations: bool,
+    ) -> HashMap<String, Result<Vec<DbNode>, String>> {
+        stream::iter(ids)
+            .map(|id| async move {
+                let result = self
+                    .get_node_int(&id, tags, relations)
+                    .await
+                    .map_err(|e| e.to_string());
+                (id, result)
+            })
+            .buffer_unordered(self.parallelism)
+            .collect()
+            .await
+    }
+
+    /// Gets the statistics of many nodes concurrently, up to `parallelism` at a time.
+    /// The result of each id is independent, a failed read doesn't fail the others.
+    pub async fn get_nodes_stats(
+        &self,
+        ids: Vec<Uuid>,
+    ) -> HashMap<Uuid, Result<Option<DbNodeStats>, String>> {
+        stream::iter(ids)
+            .map(|id| async move {
+                let result = self.get_node_stats(&id).await.map_err(|e| e.to_string());
+                (id, result)
+            })
+            .buffer_unordered(self.parallelism)
+            .collect()
+            .await
+    }
+
+    /// Gets a page of the relations of the node, optionally filtered by direction and type,
+    /// and the paging state of the next page.
+    pub async fn get_relations_page(
+        &self,
+        id: &str,
+        direction: &Option<String>,
+        relation_type: &Option<String>,
+        page_size: i32,
+        paging_state: Option<Bytes>,
+    ) -> Result<(Vec<DbRelation>, Option<Bytes>), Box<dyn std::error::Error + Sync + Send>> {
+        let uuid = Uuid::parse_str(id)?;
+        let session = &self.db_session;
+
+        let result = match (direction, relation_type) {
+            (None, None) => {
+                let ps = paged(&self.ps_relations, page_size);
+                session.execute_paged(&ps, (uuid,), paging_state).await?
+            }
+            (Some(direction), None) => {
+                let ps = paged(&self.ps_relations_direction, page_size);
+                session.execute_paged(&ps, (uuid, direction), paging_state).await?
+            }
+            (Some(direction), Some(relation)) => {
+                let ps = paged(&self.ps_relations_direction_relation, page_size);
+                session
+                    .execute_paged(&ps, (uuid, direction, relation), paging_state)
+                    .await?
+            }
+            (None, Some(relation)) => {
+                let ps = paged(&self.ps_relations_relation, page_size);
+                session.execute_paged(&ps, (uuid, relation), paging_state).await?
+            }
+        };
+
+        let mut ret = vec![];
+        let next = result.paging_state.clone();
+        if let Some(rows) = result.rows {
+            for r in rows {
+                ret.push(r.into_typed::<DbRelation>()?);
+            }
+        }
+        Ok((ret, next))
+    }
+
+    /// Gets all the rows of the node, the root row and the relations.
+    pub async fn get_node_rows(
+        &self,
+        id: &Uuid,
+    ) -> Result<Vec<DbNode>, Box<dyn std::error::Error + Sync + Send>> {
+        self.get_node_int(&id.to_string(), true, true).await
+    }
+
     pub async fn get_node_traversal(
         &self,
         id: &str,
@@ -178,13 +476,12 @@ impl ScyllaDbService {
 
         if let Some(rows) = result.rows {
             for r in rows {
-                let node;
-                if relations || tags {
-                    node = r.into_typed::<DbNode>()?;
+                let node = if relations || tags {
+                    r.into_typed::<DbNode>()?
                 } else {
                     let simple = r.into_typed::<DbNodeSimple>()?;
-                    node = DbNode::from_simple(simple);
-                }
+                    DbNode::from_simple(simple)
+                };
                 ret.push(node);
             }
         }
@@ -198,69 +495,664 @@ impl ScyllaDbService {
         Ok(ret)
     }
 
-    pub async fn save_nodes(
-        &self,
-        entries: Vec<DbNode>,
-    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    /// Saves the rows and reports the ones that failed, the caller decides if there are too many.
+    /// The nodes are added to the manifest of the ingestion first, a node that is not in the
+    /// manifest is not written since it could not be deleted. Then the rows and the index rows,
+    /// by type, tag and prefix of the root rows, are written concurrently. Failed index rows are
+    /// reported apart from the rows.
+    pub async fn save_nodes(&self, entries: Vec<DbNode>) -> SaveReport {
         let now = Instant::now();
         let sem = Arc::new(Semaphore::new(self.parallelism));
         info!("ScyllaDbService: save_nodes: Saving Nodes...");
 
-        let mut i = 0;
-        let mut handlers: Vec<JoinHandle<_>> = Vec::new();
+        let mut report = SaveReport {
+            rows: entries.len(),
+            ..Default::default()
+        };
+
+        let mut manifest_ids = HashSet::new();
+        let manifest: Vec<(String, Uuid)> = entries
+            .iter()
+            .filter(|e| manifest_ids.insert(e.uuid))
+            .map(|e| (e.ingestion_id.clone(), e.uuid))
+            .collect();
+        let mut unlisted = HashMap::new();
+        for (attempts, result) in self.execute_each(&sem, &self.ps_manifest, manifest).await {
+            report.retries += attempts.saturating_sub(1) as usize;
+            if let Err((values, e)) = result {
+                unlisted.insert(values.1, (attempts, format!("Error adding to the manifest: {}", e)));
+            }
+        }
+
+        let mut types = vec![];
+        let mut tags = vec![];
+        let mut prefixes = vec![];
+        let mut handlers: Vec<(FailedRow, JoinHandle<_>)> = Vec::new();
         for entry in entries {
+            let key = FailedRow {
+                uuid: entry.uuid,
+                direction: entry.direction.clone().unwrap_or_default(),
+                relation: entry.relation.clone().unwrap_or_default(),
+                relates_to: entry.relates_to.clone().unwrap_or_default(),
+                name: entry.name.clone(),
+                attempts: 0,
+                error: String::new(),
+            };
+            if let Some((attempts, error)) = unlisted.get(&entry.uuid) {
+                report.fail(key, *attempts, error.clone());
+                continue;
+            }
+
+            if entry.is_root() {
+                types.push((
+                    entry.ingestion_id.clone(),
+                    entry.node_type.clone(),
+                    entry.uuid,
+                    entry.name.clone(),
+                    entry.url.clone(),
+                ));
+            }
+            tags.extend(DbTagRow::from(&entry).into_iter().map(|t| {
+                (t.ingestion_id, t.tag_type, t.tag_value, t.node_type, t.uuid, t.name, t.url)
+            }));
+            prefixes.extend(DbPrefixRow::from(&entry).into_iter().map(|p| {
+                (p.ingestion_id, p.prefix, p.exact, p.depth, p.uuid, p.name, p.url, p.node_type)
+            }));
+
             let session = self.db_session.clone();
             let prepared = self.ps.clone();
+            let policy = self.write_policy;
             let permit = sem.clone().acquire_owned().await;
             debug!("save_nodes: Creating Task...");
-            handlers.push(tokio::task::spawn(async move {
+            handlers.push((key, tokio::task::spawn(async move {
                 debug!("save_nodes: Running query for node {}", entry.name);
-                let result = session
-                    .execute(
-                        &prepared,
-                        (
-                            entry.uuid,
-                            entry.direction.unwrap_or_default(),
-                            entry.relation.unwrap_or_default(),
-                            entry.relates_to.unwrap_or_default(),
-                            entry.name,
-                            entry.ingestion_id,
-                            entry.url,
-                            entry.node_type,
-                            entry.tags.unwrap_or_default(),
-                        ),
-                    )
-                    .await;
+                let values = (
+                    entry.uuid,
+                    entry.direction.unwrap_or_default(),
+                    entry.relation.unwrap_or_default(),
+                    entry.relates_to.unwrap_or_default(),
+                    entry.name,
+                    entry.ingestion_id,
+                    entry.url,
+                    entry.node_type,
+                    entry.tags.unwrap_or_default(),
+                );
+                let result = execute_with_retry(&session, &prepared, &values, &policy).await;
 
                 let _permit = permit;
 
                 result
-            }));
+            })));
             debug!("save_nodes: Task Created");
-            i += 1;
         }
 
         info!(
             "ScyllaDbService: save_nodes: Waiting for {} tasks to complete...",
-            i
+            handlers.len()
         );
 
-        let mut error_count = 0;
-        for thread in handlers {
-            match thread.await? {
+        // the index rows are written while the node rows are still written by the tasks above
+        let (types, tags, prefixes) = futures::join!(
+            self.execute_each(&sem, &self.ps_type, types),
+            self.execute_each(&sem, &self.ps_tag, tags),
+            self.execute_each(&sem, &self.ps_prefix, prefixes),
+        );
+        let indexes = index_results(types)
+            .into_iter()
+            .chain(index_results(tags))
+            .chain(index_results(prefixes));
+        for (attempts, result) in indexes {
+            report.index_rows += 1;
+            report.retries += attempts.saturating_sub(1) as usize;
+            if let Err(e) = result {
+                error!("save_nodes: Error Writing Index Row. {}", e);
+                report.fail_index(e);
+            }
+        }
+
+        for (key, thread) in handlers {
+            let (attempts, result) = match thread.await {
+                Ok(r) => r,
+                Err(e) => (0, Err(e.to_string())),
+            };
+            report.retries += attempts.saturating_sub(1) as usize;
+            match result {
                 Err(e) => {
                     error!("save_nodes: Error Executing Query. {:?}", e);
-                    error_count += 1;
+                    report.fail(key, attempts, e);
+                }
+                Ok(r) => {
+                    debug!("save_nodes: Query Result: {:?}", r);
+                    report.written += 1;
                 }
-                Ok(r) => debug!("save_nodes: Query Result: {:?}", r),
             };
         }
 
         let elapsed = now.elapsed();
         info!(
-            "ScyllaDbService: save_nodes: {} save nodes tasks completed. ERRORS: {}. Took: {:.2?}",
-            i, error_count, elapsed
+            "ScyllaDbService: save_nodes: {} rows and {} index rows saved. ERRORS: {}. INDEX ERRORS: {}. RETRIES: {}. Took: {:.2?}",
+            report.rows, report.index_rows, report.failed, report.index_failed, report.retries, elapsed
         );
+
+        report
+    }
+
+    /// True if the ingestion has any node in its manifest.
+    pub async fn has_ingestion_nodes(
+        &self,
+        ingestion_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .query(HAS_MANIFEST_QUERY, (ingestion_id,))
+            .await?;
+        Ok(result.rows.is_some_and(|rows| !rows.is_empty()))
+    }
+
+    /// Gets a page of the ids of the nodes of an ingestion and the paging state of the next page.
+    pub async fn get_ingestion_nodes(
+        &self,
+        ingestion_id: &str,
+        paging_state: Option<Bytes>,
+    ) -> Result<(Vec<Uuid>, Option<Bytes>), Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .execute_paged(&self.ps_manifest_page, (ingestion_id,), paging_state)
+            .await?;
+
+        let mut ids = vec![];
+        let next = result.paging_state.clone();
+        if let Some(rows) = result.rows {
+            for r in rows {
+                let (id,) = r.into_typed::<(Uuid,)>()?;
+                ids.push(id);
+            }
+        }
+        Ok((ids, next))
+    }
+
+    /// Deletes all the rows of the nodes and their statistics, returns the number of nodes deleted.
+    pub async fn delete_nodes(
+        &self,
+        ids: Vec<Uuid>,
+    ) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+        let values: Vec<(Uuid,)> = ids.into_iter().map(|id| (id,)).collect();
+        self.execute_all(&self.ps_delete_stats, values.clone(), "delete_stats")
+            .await?;
+        self.execute_all(&self.ps_delete, values, "delete_nodes").await
+    }
+
+    /// Deletes the index rows of the type, the tags and the prefixes of the nodes, `nodes` are
+    /// the root rows as they were saved. Returns the number of index rows deleted.
+    pub async fn delete_node_indexes(
+        &self,
+        nodes: &[DbNode],
+    ) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+        let types = nodes
+            .iter()
+            .filter(|n| n.is_root())
+            .map(|n| (n.ingestion_id.clone(), n.node_type.clone(), n.uuid))
+            .collect();
+        let tags = nodes
+            .iter()
+            .flat_map(DbTagRow::from)
+            .map(|t| (t.ingestion_id, t.tag_type, t.tag_value, t.node_type, t.uuid))
+            .collect();
+        let prefixes = nodes
+            .iter()
+            .flat_map(DbPrefixRow::from)
+            .map(|p| (p.ingestion_id, p.prefix, p.exact, p.depth, p.uuid))
+            .collect();
+        let mut deleted = self.execute_all(&self.ps_delete_type, types, "delete_type_index").await?;
+        deleted += self.execute_all(&self.ps_delete_tag, tags, "delete_tag_index").await?;
+        deleted += self
+            .execute_all(&self.ps_delete_prefix, prefixes, "delete_prefix_index")
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Gets up to `limit` nodes of the ingestion with the prefix, the nodes whose whole word
+    /// or URL segment is the prefix first, then by depth.
+    pub async fn search_prefix(
+        &self,
+        ingestion_id: &str,
+        prefix: &str,
+        limit: i32,
+    ) -> Result<Vec<DbNode>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .execute(&self.ps_search_prefix, (ingestion_id, prefix, limit))
+            .await?;
+
+        let mut ret = vec![];
+        if let Some(rows) = result.rows {
+            for r in rows {
+                ret.push(DbNode::from_simple(r.into_typed::<DbNodeSimple>()?));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Gets a page of the nodes of the ingestion of a type, sorted by id,
+    /// and the paging state of the next page.
+    pub async fn get_type_nodes_page(
+        &self,
+        ingestion_id: &str,
+        node_type: &str,
+        page_size: i32,
+        paging_state: Option<Bytes>,
+    ) -> Result<(Vec<DbNode>, Option<Bytes>), Box<dyn std::error::Error + Sync + Send>> {
+        let ps = paged(&self.ps_type_nodes, page_size);
+        let result = self
+            .db_session
+            .execute_paged(&ps, (ingestion_id, node_type), paging_state)
+            .await?;
+
+        let mut ret = vec![];
+        let next = result.paging_state.clone();
+        if let Some(rows) = result.rows {
+            for r in rows {
+                ret.push(DbNode::from_simple(r.into_typed::<DbNodeSimple>()?));
+            }
+        }
+        Ok((ret, next))
+    }
+
+    /// Gets the node types of the ingestion and their number of nodes.
+    pub async fn get_type_counts(
+        &self,
+        ingestion_id: &str,
+    ) -> Result<Vec<(String, i64)>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .query(GET_TYPE_COUNTS_QUERY, (ingestion_id,))
+            .await?;
+
+        let mut ret = vec![];
+        if let Some(rows) = result.rows {
+            for r in rows {
+                ret.push(r.into_typed::<(String, i64)>()?);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Saves the number of nodes of each type of the ingestion, the types that are not
+    /// in `counts` anymore are deleted.
+    pub async fn save_type_counts(
+        &self,
+        ingestion_id: &str,
+        counts: HashMap<String, i64>,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        let removed = self
+            .get_type_counts(ingestion_id)
+            .await?
+            .into_iter()
+            .filter(|(node_type, _)| !counts.contains_key(node_type))
+            .map(|(node_type, _)| (ingestion_id.to_owned(), node_type))
+            .collect();
+        let values = counts
+            .into_iter()
+            .map(|(node_type, nodes)| (ingestion_id.to_owned(), node_type, nodes))
+            .collect();
+        self.execute_all(&self.ps_type_count, values, "save_type_counts").await?;
+        self.execute_all(&self.ps_delete_type_count, removed, "delete_type_counts").await?;
         Ok(())
     }
+
+    /// Gets a page of the nodes of the ingestion with the tag, optionally of a node type,
+    /// and the paging state of the next page. The nodes are sorted by type and id.
+    pub async fn search_tag_page(
+        &self,
+        ingestion_id: &str,
+        tag: (&str, &str),
+        node_type: &Option<String>,
+        page_size: i32,
+        paging_state: Option<Bytes>,
+    ) -> Result<(Vec<DbNode>, Option<Bytes>), Box<dyn std::error::Error + Sync + Send>> {
+        let (tag_type, tag_value) = tag;
+        let session = &self.db_session;
+        let result = match node_type {
+            Some(node_type) => {
+                let ps = paged(&self.ps_search_tag_type, page_size);
+                session
+                    .execute_paged(&ps, (ingestion_id, tag_type, tag_value, node_type), paging_state)
+                    .await?
+            }
+            None => {
+                let ps = paged(&self.ps_search_tag, page_size);
+                session
+                    .execute_paged(&ps, (ingestion_id, tag_type, tag_value), paging_state)
+                    .await?
+            }
+        };
+
+        let mut ret = vec![];
+        let next = result.paging_state.clone();
+        if let Some(rows) = result.rows {
+            for r in rows {
+                ret.push(DbNode::from_simple(r.into_typed::<DbNodeSimple>()?));
+            }
+        }
+        Ok((ret, next))
+    }
+
+    /// Saves the statistics of the nodes, returns the number of nodes saved.
+    pub async fn save_node_stats(
+        &self,
+        stats: Vec<DbNodeStats>,
+    ) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+        let values = stats
+            .into_iter()
+            .map(|s| (s.uuid, s.ingestion_id, s.children, s.descendants, s.depth, s.types))
+            .collect();
+        self.execute_all(&self.ps_stats, values, "save_node_stats").await
+    }
+
+    pub async fn get_node_stats(
+        &self,
+        id: &Uuid,
+    ) -> Result<Option<DbNodeStats>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self.db_session.query(GET_STATS_QUERY, (id,)).await?;
+        match result.rows.and_then(|rows| rows.into_iter().next()) {
+            Some(row) => Ok(Some(row.into_typed::<DbNodeStats>()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Deletes single rows, returns the number of rows deleted.
+    pub async fn delete_rows(
+        &self,
+        keys: Vec<DbRowKey>,
+    ) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+        let values = keys
+            .into_iter()
+            .map(|k| (k.uuid, k.direction, k.relation, k.relates_to))
+            .collect();
+        self.execute_all(&self.ps_delete_row, values, "delete_rows").await
+    }
+
+    /// Executes the query for all the values in parallel, failing if any of them fails.
+    async fn execute_all<V: ValueList + std::fmt::Debug + Send + Sync + 'static>(
+        &self,
+        prepared: &Arc<PreparedStatement>,
+        values: Vec<V>,
+        name: &str,
+    ) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+        let now = Instant::now();
+        let sem = Arc::new(Semaphore::new(self.parallelism));
+
+        let mut done = 0;
+        let mut errors = vec![];
+        for (_, result) in self.execute_each(&sem, prepared, values).await {
+            match result {
+                Ok(()) => done += 1,
+                Err((v, e)) => errors.push(format!("Error executing query for {:?}: {}", v, e)),
+            }
+        }
+
+        info!(
+            "ScyllaDbService: {}: {} queries executed. ERRORS: {}. Took: {:.2?}",
+            name,
+            done,
+            errors.len(),
+            now.elapsed()
+        );
+
+        if let Some(e) = errors.first() {
+            return Err(format!("{} queries failed. {}", errors.len(), e).into());
+        }
+        Ok(done)
+    }
+
+    /// Executes the statement with every value, up to the permits of `sem` at a time and
+    /// retrying transient errors. Returns the attempts and the result of each value, in order,
+    /// the value is returned with the error.
+    async fn execute_each<V: ValueList + Send + Sync + 'static>(
+        &self,
+        sem: &Arc<Semaphore>,
+        prepared: &Arc<PreparedStatement>,
+        values: Vec<V>,
+    ) -> Vec<WriteResult<V>> {
+        let mut handlers: Vec<JoinHandle<_>> = Vec::new();
+        for v in values {
+            let session = self.db_session.clone();
+            let prepared = prepared.clone();
+            let policy = self.write_policy;
+            let permit = sem.clone().acquire_owned().await;
+            handlers.push(tokio::task::spawn(async move {
+                let (attempts, result) = execute_with_retry(&session, &prepared, &v, &policy).await;
+                let _permit = permit;
+                (attempts, result.map(|_| ()).map_err(|e| (v, e)))
+            }));
+        }
+
+        let mut ret = vec![];
+        for thread in handlers {
+            match thread.await {
+                Ok(r) => ret.push(r),
+                Err(e) => error!("execute_each: task failed: {}", e),
+            }
+        }
+        ret
+    }
+
+    /// Deletes the manifest and the node types of the ingestion.
+    pub async fn delete_ingestion_manifest(
+        &self,
+        ingestion_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        self.db_session
+            .query(DELETE_TYPE_COUNTS_QUERY, (ingestion_id,))
+            .await?;
+        self.db_session
+            .query(DELETE_MANIFEST_QUERY, (ingestion_id,))
+            .await?;
+        // a deleted version can be loaded again
+        self.db_session
+            .query(DELETE_VERSION_QUERY, (ingestion_id,))
+            .await?;
+        Ok(())
+    }
+
+    /// Saves the status of a job as JSON, it expires after `ttl` seconds.
+    /// The kind and status are also saved apart to find the jobs of an ingestion.
+    pub async fn save_job(
+        &self,
+        id: &Uuid,
+        ingestion_id: &str,
+        kind: &str,
+        status: &str,
+        job: &str,
+        ttl: i32,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        self.db_session
+            .query(INSERT_JOB_QUERY, (id, ingestion_id, kind, status, job, ttl))
+            .await?;
+        Ok(())
+    }
+
+    /// Ids, kinds and statuses of the jobs of an ingestion saved by any replica.
+    pub async fn get_ingestion_jobs(
+        &self,
+        ingestion_id: &str,
+    ) -> Result<Vec<(Uuid, String, String)>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .query(GET_INGESTION_JOBS_QUERY, (ingestion_id,))
+            .await?;
+
+        let mut ret = vec![];
+        if let Some(rows) = result.rows {
+            for r in rows {
+                let (id, kind, status) = r.into_typed::<(Uuid, Option<String>, Option<String>)>()?;
+                ret.push((id, kind.unwrap_or_default(), status.unwrap_or_default()));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Gets the JSON status of a job saved by any replica.
+    pub async fn get_job(
+        &self,
+        id: &Uuid,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self.db_session.query(GET_JOB_QUERY, (id,)).await?;
+        match result.rows.and_then(|rows| rows.into_iter().next()) {
+            Some(row) => Ok(row.into_typed::<(Option<String>,)>()?.0),
+            None => Ok(None),
+        }
+    }
+
+    /// Requests the replica that runs the job to cancel it. Returns false if the job doesn't exist.
+    pub async fn request_cancel(
+        &self,
+        id: &Uuid,
+        ttl: i32,
+    ) -> Result<bool, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self.db_session.query(CANCEL_JOB_QUERY, (ttl, id)).await?;
+        applied(result, "cancel")
+    }
+
+    /// Ids of the jobs whose cancel was requested.
+    pub async fn get_cancel_requests(
+        &self,
+        ids: Vec<Uuid>,
+    ) -> Result<Vec<Uuid>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .query(GET_CANCEL_REQUESTS_QUERY, (ids,))
+            .await?;
+
+        let mut ret = vec![];
+        if let Some(rows) = result.rows {
+            for r in rows {
+                let (id, cancel) = r.into_typed::<(Uuid, Option<bool>)>()?;
+                if cancel.unwrap_or_default() {
+                    ret.push(id);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Gets the ingestion the alias points to.
+    pub async fn get_alias(
+        &self,
+        alias: &str,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Sync + Send>> {
+        let mut query = Query::new(GET_ALIAS_QUERY);
+        query.set_consistency(Consistency::Quorum);
+        let result = self.db_session.query(query, (alias,)).await?;
+        match result.rows.and_then(|rows| rows.into_iter().next()) {
+            Some(row) => Ok(Some(row.into_typed::<(String,)>()?.0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Points the alias to the ingestion if it still points to `previous`, it is a lightweight
+    /// transaction so concurrent swaps can't overwrite each other. Returns false if the alias changed.
+    pub async fn swap_alias(
+        &self,
+        alias: &str,
+        previous: Option<&str>,
+        ingestion_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Sync + Send>> {
+        let result = match previous {
+            Some(previous) => {
+                self.db_session
+                    .query(UPDATE_ALIAS_QUERY, (ingestion_id, alias, previous))
+                    .await?
+            }
+            None => {
+                self.db_session
+                    .query(INSERT_ALIAS_QUERY, (alias, ingestion_id))
+                    .await?
+            }
+        };
+        applied(result, "alias swap")
+    }
+
+    /// Alias of the load that claimed the ingestion ID, if any.
+    pub async fn get_version_alias(
+        &self,
+        ingestion_id: &str,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .query(GET_VERSION_ALIAS_QUERY, (ingestion_id,))
+            .await?;
+        match result.rows.and_then(|rows| rows.into_iter().next()) {
+            Some(row) => Ok(row.into_typed::<(Option<String>,)>()?.0),
+            None => Ok(None),
+        }
+    }
+
+    /// Claims the ingestion ID for a load with an alias. Returns false if another load
+    /// already claimed it.
+    pub async fn claim_version(
+        &self,
+        ingestion_id: &str,
+        alias: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Sync + Send>> {
+        let result = self
+            .db_session
+            .query(CLAIM_VERSION_QUERY, (ingestion_id, alias))
+            .await?;
+        applied(result, "version claim")
+    }
+}
+
+/// The `[applied]` column of a lightweight transaction.
+fn applied(
+    result: QueryResult,
+    query: &str,
+) -> Result<bool, Box<dyn std::error::Error + Sync + Send>> {
+    let applied = result
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.columns.into_iter().next().flatten())
+        .and_then(|value| value.as_boolean())
+        .ok_or(format!("Missing [applied] column in the {} result", query))?;
+    Ok(applied)
+}
+
+/// Copy of the prepared statement with the page size of the request.
+fn paged(prepared: &PreparedStatement, page_size: i32) -> PreparedStatement {
+    let mut ps = prepared.clone();
+    ps.set_page_size(page_size);
+    ps
+}
+
+/// Results of the index rows with the values of the failed ones in the error.
+fn index_results<V: std::fmt::Debug>(
+    results: Vec<WriteResult<V>>,
+) -> Vec<(u32, Result<(), String>)> {
+    results
+        .into_iter()
+        .map(|(attempts, r)| (attempts, r.map_err(|(v, e)| format!("{:?}: {}", v, e))))
+        .collect()
+}
+
+/// Executes the query retrying transient errors with exponential backoff.
+/// Returns the number of attempts and the result.
+async fn execute_with_retry(
+    session: &Session,
+    prepared: &PreparedStatement,
+    values: impl ValueList,
+    policy: &WritePolicy,
+) -> (u32, Result<QueryResult, String>) {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match session.execute(prepared, &values).await {
+            Ok(r) => return (attempts, Ok(r)),
+            Err(e) if attempts <= policy.retries && is_transient(&e) => {
+                let backoff = policy.backoff_ms * 2u64.pow(attempts - 1);
+                debug!("Transient error {:?}. Retrying in {} ms", e, backoff);
+                tokio::time::sleep(Duration::from_millis(backoff)).await;
+            }
+            Err(e) => return (attempts, Err(e.to_string())),
+        }
+    }
 }
@@ -0,0 +1,394 @@
+// Export of a subtree to the source JSON format
+use crate::data::source_model::Tag;
+use crate::db::model::DbNode;
+use crate::db::scylladb::ScyllaDbService;
+use crate::{DIR, REL};
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io;
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Bytes written before a chunk is sent to the response
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct ExportOptions {
+    /// Levels of children below the node, all if None
+    pub max_depth: Option<usize>,
+    /// Children of a node read at a time
+    pub concurrency: usize,
+}
+
+/// A node read from its rows, with the ids of its children and its custom relations
+struct ExportNode {
+    id: Uuid,
+    name: String,
+    node_type: String,
+    url: String,
+    tags: Vec<Tag>,
+    children: Vec<Uuid>,
+    relations: Vec<(String, Uuid, Vec<Tag>)>,
+}
+
+impl ExportNode {
+    /// The node from its root row and its relation rows, None if the root row is missing.
+    fn from(rows: Vec<DbNode>) -> Option<Self> {
+        let root = rows.iter().find(|r| r.is_root())?;
+        let mut node = ExportNode {
+            id: root.uuid,
+            name: root.name.clone(),
+            node_type: root.node_type.clone(),
+            url: root.url.clone(),
+            tags: tags(&root.tags),
+            children: vec![],
+            relations: vec![],
+        };
+
+        let out = DIR::OUT.to_string();
+        let child = REL::ISCHILD.to_string();
+        for r in rows.iter().filter(|r| r.direction.as_deref() == Some(out.as_str())) {
+            let relation = r.relation.clone().unwrap_or_default();
+            let target = match r.relates_to.as_deref().map(Uuid::parse_str) {
+                Some(Ok(target)) => target,
+                _ => continue,
+            };
+            if relation == child {
+                node.children.push(target);
+            } else {
+                node.relations.push((relation, target, tags(&r.tags)));
+            }
+        }
+        Some(node)
+    }
+}
+
+fn tags(tags: &Option<Vec<(String, String)>>) -> Vec<Tag> {
+    tags.iter()
+        .flatten()
+        .map(|(type_field, value)| Tag {
+            type_field: type_field.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct ExportRelation<'a> {
+    #[serde(rename = "type")]
+    type_field: &'a str,
+    source: Vec<&'a str>,
+    target: Vec<&'a str>,
+    tags: &'a [Tag],
+}
+
+/// Reads the node with all its rows, None if it doesn't exist.
+pub async fn read_node(
+    db: &ScyllaDbService,
+    id: &Uuid,
+) -> Result<Option<Vec<DbNode>>, Box<dyn std::error::Error + Sync + Send>> {
+    let rows = db.get_node_rows(id).await?;
+    Ok(rows.iter().any(|r| r.is_root()).then_some(rows))
+}
+
+/// Writes the subtree of the node, read from `rows`, as a source `File` document to `tx`
+/// while it is read, depth first. The children of a node are read concurrently and sorted
+/// by name. The custom relations between nodes of the subtree are written at the end, their
+/// paths start at the node, relations to nodes out of the subtree are skipped.
+/// A read error is sent to `tx`, the document is left incomplete.
+pub async fn export(
+    db: &ScyllaDbService,
+    rows: Vec<DbNode>,
+    options: ExportOptions,
+    tx: Sender<Result<Bytes, io::Error>>,
+) {
+    let mut writer = ChunkWriter {
+        buffer: Vec::with_capacity(CHUNK_SIZE),
+        tx: tx.clone(),
+    };
+    let concurrency = options.concurrency;
+    let read = |ids| read_children(db, ids, concurrency);
+    let result = match ExportNode::from(rows) {
+        Some(root) => write_file(read, root, &options, &mut writer).await,
+        None => Err("Node not found".into()),
+    };
+
+    if let Err(e) = result {
+        info!("export: export stopped: {}", e);
+        // the client is gone if the channel is closed, there is no one to tell
+        let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+    }
+}
+
+/// Writes the document, `read` reads the children of a node.
+async fn write_file<R, F>(
+    mut read: R,
+    root: ExportNode,
+    options: &ExportOptions,
+    writer: &mut ChunkWriter,
+) -> Result<(), Box<dyn std::error::Error + Sync + Send>>
+where
+    R: FnMut(Vec<Uuid>) -> F,
+    F: Future<Output = Result<Vec<ExportNode>, Box<dyn std::error::Error + Sync + Send>>>,
+{
+    // paths are relative to the parent of the exported node
+    let base = root.url.rsplit_once('/').map(|(parent, _)| parent.len() + 1).unwrap_or(0);
+    let root_id = root.id;
+
+    let mut urls: HashMap<Uuid, String> = HashMap::new();
+    let mut relations: Vec<(Uuid, String, Uuid, Vec<Tag>)> = vec![];
+    let mut visited = HashSet::from([root.id]);
+
+    writer.write(b"{\"nodes\":[").await?;
+    // the children of every open node still to write, and if none was written yet
+    let mut stack: Vec<(std::vec::IntoIter<ExportNode>, bool)> = vec![];
+    let mut next = Some(root);
+    loop {
+        if let Some(node) = next.take() {
+            write_node_start(&node, writer).await?;
+            let expand = options.max_depth.is_none_or(|max| stack.len() < max);
+            let children: Vec<Uuid> = node
+                .children
+                .iter()
+                .filter(|c| expand && visited.insert(**c))
+                .copied()
+                .collect();
+
+            urls.insert(node.id, node.url[base.min(node.url.len())..].to_owned());
+            relations.extend(
+                node.relations
+                    .into_iter()
+                    .map(|(relation, target, tags)| (node.id, relation, target, tags)),
+            );
+
+            let mut children = read(children).await?;
+            children.sort_by(|a, b| a.name.cmp(&b.name));
+            stack.push((children.into_iter(), true));
+        }
+
+        let (children, first) = match stack.last_mut() {
+            Some(frame) => frame,
+            None => break,
+        };
+        match children.next() {
+            Some(child) => {
+                if !*first {
+                    writer.write(b",").await?;
+                }
+                *first = false;
+                next = Some(child);
+            }
+            None => {
+                stack.pop();
+                writer.write(b"]}").await?;
+            }
+        }
+    }
+
+    writer.write(b"],\"relations\":[").await?;
+    let mut first = true;
+    for (source, relation, target, tags) in &relations {
+        let (source, target) = match (urls.get(source), urls.get(target)) {
+            (Some(source), Some(target)) => (source, target),
+            _ => continue,
+        };
+        if !first {
+            writer.write(b",").await?;
+        }
+        first = false;
+        let relation = ExportRelation {
+            type_field: relation,
+            source: source.split('/').collect(),
+            target: target.split('/').collect(),
+            tags,
+        };
+        writer.write(&serde_json::to_vec(&relation)?).await?;
+    }
+    writer.write(b"]}").await?;
+    writer.flush().await?;
+
+    info!("export: {} nodes below {} exported", urls.len(), root_id);
+    Ok(())
+}
+
+/// Writes the node up to its list of children, which is closed by the caller.
+async fn write_node_start(
+    node: &ExportNode,
+    writer: &mut ChunkWriter,
+) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    writer.write(b"{\"name\":").await?;
+    writer.write(&serde_json::to_vec(&node.name)?).await?;
+    writer.write(b",\"type\":").await?;
+    writer.write(&serde_json::to_vec(&node.node_type)?).await?;
+    if !node.tags.is_empty() {
+        writer.write(b",\"tags\":").await?;
+        writer.write(&serde_json::to_vec(&node.tags)?).await?;
+    }
+    writer.write(b",\"children\":[").await?;
+    Ok(())
+}
+
+/// Reads the children, up to `concurrency` at a time. Children without a root row are skipped.
+async fn read_children(
+    db: &ScyllaDbService,
+    ids: Vec<Uuid>,
+    concurrency: usize,
+) -> Result<Vec<ExportNode>, Box<dyn std::error::Error + Sync + Send>> {
+    debug!("export: reading {} children", ids.len());
+    let rows: Vec<_> = stream::iter(ids)
+        .map(|id| async move { db.get_node_rows(&id).await })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut children = vec![];
+    for r in rows {
+        children.extend(ExportNode::from(r?));
+    }
+    Ok(children)
+}
+
+/// Buffers the document and sends it to the response in chunks.
+struct ChunkWriter {
+    buffer: Vec<u8>,
+    tx: Sender<Result<Bytes, io::Error>>,
+}
+
+impl ChunkWriter {
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+        self.tx
+            .send(Ok(Bytes::from(chunk)))
+            .await
+            .map_err(|_| "export receiver closed".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::model::get_id_from_url;
+    use crate::data::source_model::{NodeRecord, Op, Relation};
+    use crate::data::source_parser::{parse_file, SourceItem};
+    use tokio::sync::mpsc;
+
+    fn id(url: &str) -> Uuid {
+        get_id_from_url("test".to_owned(), url.to_owned())
+    }
+
+    fn tag(value: &str) -> Tag {
+        Tag {
+            type_field: "owner".to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    /// The rows of the node `url` with its children and custom relations, like an ingestion.
+    fn rows(url: &str, tags: Vec<Tag>, children: &[&str], custom: &[&str]) -> Vec<DbNode> {
+        let name = url.rsplit('/').next().unwrap();
+        let mut rows = vec![DbNode::root("test".to_owned(), url.to_owned(), name.to_owned(), "t".to_owned(), tags)];
+        let relation = |relation: &str, target: &str| {
+            DbNode::relation(
+                id(url),
+                "test".to_owned(),
+                DIR::OUT.to_string(),
+                relation.to_owned(),
+                id(target).to_string(),
+                target.rsplit('/').next().unwrap().to_owned(),
+            )
+        };
+        rows.extend(children.iter().map(|c| relation(&REL::ISCHILD.to_string(), c)));
+        rows.extend(custom.iter().map(|t| relation("custom", t)));
+        rows
+    }
+
+    fn record(name: &str, parent: &[&str], tags: Option<Vec<Tag>>) -> SourceItem {
+        SourceItem::NodeRecord(NodeRecord {
+            name: name.to_owned(),
+            type_field: "t".to_owned(),
+            parent: parent.iter().map(|p| p.to_string()).collect(),
+            tags,
+            op: Op::Added,
+        })
+    }
+
+    #[test]
+    fn export_parses_back_into_the_same_tree() {
+        let mut store: HashMap<Uuid, Vec<DbNode>> = HashMap::new();
+        for node in [
+            // `a` is below `root`, the paths of the export start at `a`
+            rows("root/a", vec![], &["root/a/c", "root/a/b"], &[]),
+            rows("root/a/b", vec![tag("x")], &["root/a/b/d"], &["root/a/c", "root/other"]),
+            rows("root/a/c", vec![], &[], &[]),
+            rows("root/a/b/d", vec![tag("y")], &[], &[]),
+        ] {
+            store.insert(node[0].uuid, node);
+        }
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let mut writer = ChunkWriter {
+            buffer: vec![],
+            tx,
+        };
+        let read = |ids: Vec<Uuid>| {
+            let children = ids
+                .iter()
+                .filter_map(|id| ExportNode::from(store[id].clone()))
+                .collect();
+            async move { Ok(children) }
+        };
+        let root = ExportNode::from(store[&id("root/a")].clone()).unwrap();
+        let options = ExportOptions {
+            max_depth: None,
+            concurrency: 1,
+        };
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(write_file(read, root, &options, &mut writer))
+            .unwrap();
+        drop(writer);
+
+        let mut document = vec![];
+        while let Ok(chunk) = rx.try_recv() {
+            document.extend_from_slice(&chunk.unwrap());
+        }
+
+        let (tx, mut rx) = mpsc::channel(16);
+        parse_file(document.as_slice(), tx).unwrap();
+        let mut items = vec![];
+        while let Ok(item) = rx.try_recv() {
+            items.push(item);
+        }
+
+        assert_eq!(
+            items,
+            vec![
+                record("d", &["a", "b"], Some(vec![tag("y")])),
+                record("b", &["a"], Some(vec![tag("x")])),
+                record("c", &["a"], None),
+                record("a", &[], None),
+                // the relation to a node out of the subtree is skipped
+                SourceItem::Relation(Relation {
+                    type_field: "custom".to_owned(),
+                    source: vec!["a".to_owned(), "b".to_owned()],
+                    target: vec!["a".to_owned(), "c".to_owned()],
+                    tags: Some(vec![]),
+                    op: Op::Added,
+                }),
+            ]
+        );
+    }
+
+}
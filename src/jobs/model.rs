@@ -0,0 +1,248 @@
+use crate::db::model::SaveReport;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use strum_macros::Display;
+use uuid::Uuid;
+
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FileState {
+    Queued,
+    Reading,
+    Flattening,
+    Persisting,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl FileState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, FileState::Done | FileState::Failed | FileState::Cancelled)
+    }
+}
+
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum JobKind {
+    Ingestion,
+    Delete,
+    Stats,
+}
+
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    /// Cancel requested, the writes in progress are being finished
+    Cancelling,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileStatus {
+    pub file: String,
+    pub state: FileState,
+    pub relations: usize,
+    pub rows: usize,
+    /// Nodes and relations removed by a delta
+    pub removed: usize,
+    pub read_ms: Option<u64>,
+    pub flatten_ms: Option<u64>,
+    pub persist_ms: Option<u64>,
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+    pub report: Option<SaveReport>,
+}
+
+impl FileStatus {
+    pub fn new(file: String) -> Self {
+        Self {
+            file,
+            state: FileState::Queued,
+            relations: 0,
+            rows: 0,
+            removed: 0,
+            read_ms: None,
+            flatten_ms: None,
+            persist_ms: None,
+            duration_ms: None,
+            error: None,
+            report: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub ingestion_id: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileStatus>,
+    /// Nodes deleted so far by a delete job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<usize>,
+    /// Nodes whose statistics were computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Alias switched to this ingestion when all the files are done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Delete job of the version replaced by this one, or of this version if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_job_id: Option<Uuid>,
+}
+
+impl Job {
+    pub fn new(kind: JobKind, ingestion_id: String, files: &[String]) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind,
+            ingestion_id,
+            status: JobStatus::Running,
+            created_at: Utc::now(),
+            finished_at: None,
+            duration_ms: None,
+            files: files.iter().map(|f| FileStatus::new(f.clone())).collect(),
+            deleted: match kind {
+                JobKind::Delete => Some(0),
+                JobKind::Ingestion | JobKind::Stats => None,
+            },
+            stats: None,
+            error: None,
+            alias: None,
+            gc_job_id: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.status, JobStatus::Running | JobStatus::Cancelling)
+    }
+
+    fn close(&mut self, status: JobStatus) {
+        let now = Utc::now();
+        self.status = status;
+        self.duration_ms = Some((now - self.created_at).num_milliseconds().max(0) as u64);
+        self.finished_at = Some(now);
+    }
+
+    pub fn finish(&mut self) {
+        if self.status == JobStatus::Cancelling {
+            return self.cancel();
+        }
+        if self.is_finished() {
+            return;
+        }
+        let failed =
+            self.error.is_some() || self.files.iter().any(|f| f.state == FileState::Failed);
+        // files that never started were skipped, count them as cancelled
+        for f in self.files.iter_mut().filter(|f| !f.state.is_finished()) {
+            f.state = FileState::Cancelled;
+        }
+        self.close(if failed { JobStatus::Failed } else { JobStatus::Done });
+    }
+
+    pub fn cancel(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+        for f in self.files.iter_mut().filter(|f| !f.state.is_finished()) {
+            f.state = FileState::Cancelled;
+        }
+        self.close(JobStatus::Cancelled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(files: &[&str]) -> Job {
+        let files: Vec<String> = files.iter().map(|f| f.to_string()).collect();
+        Job::new(JobKind::Ingestion, "test".to_owned(), &files)
+    }
+
+    fn states(job: &Job) -> Vec<FileState> {
+        job.files.iter().map(|f| f.state).collect()
+    }
+
+    #[test]
+    fn finish_is_done_when_all_the_files_are_done() {
+        let mut job = job(&["a", "b"]);
+        job.files.iter_mut().for_each(|f| f.state = FileState::Done);
+        job.finish();
+
+        assert_eq!(job.status, JobStatus::Done);
+        assert!(job.is_finished());
+        assert!(job.finished_at.is_some());
+        assert!(job.duration_ms.is_some());
+    }
+
+    #[test]
+    fn finish_fails_on_a_failed_file_or_an_error() {
+        let mut failed_file = job(&["a", "b", "c"]);
+        failed_file.files[0].state = FileState::Done;
+        failed_file.files[1].state = FileState::Failed;
+        failed_file.finish();
+
+        assert_eq!(failed_file.status, JobStatus::Failed);
+        // the file that never started is counted as cancelled
+        assert_eq!(
+            states(&failed_file),
+            vec![FileState::Done, FileState::Failed, FileState::Cancelled]
+        );
+
+        let mut error = job(&[]);
+        error.error = Some("alias changed".to_owned());
+        error.finish();
+        assert_eq!(error.status, JobStatus::Failed);
+    }
+
+    #[test]
+    fn finish_of_a_cancelling_job_cancels_it() {
+        let mut job = job(&["a", "b"]);
+        job.files[0].state = FileState::Done;
+        job.files[1].state = FileState::Persisting;
+        job.status = JobStatus::Cancelling;
+        assert!(!job.is_finished());
+
+        job.finish();
+
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert_eq!(states(&job), vec![FileState::Done, FileState::Cancelled]);
+    }
+
+    #[test]
+    fn cancel_after_finish_is_a_no_op() {
+        let mut job = job(&["a"]);
+        job.files[0].state = FileState::Done;
+        job.finish();
+        let finished_at = job.finished_at;
+
+        job.cancel();
+        job.finish();
+
+        assert_eq!(job.status, JobStatus::Done);
+        assert_eq!(job.finished_at, finished_at);
+        assert_eq!(states(&job), vec![FileState::Done]);
+    }
+
+    #[test]
+    fn new_counts_the_deleted_nodes_of_a_delete_job() {
+        assert_eq!(Job::new(JobKind::Delete, "test".to_owned(), &[]).deleted, Some(0));
+        assert_eq!(job(&["a"]).deleted, None);
+        assert_eq!(states(&job(&["a", "b"])), vec![FileState::Queued, FileState::Queued]);
+    }
+}
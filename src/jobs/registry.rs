@@ -0,0 +1,164 @@
+use crate::jobs::model::{FileStatus, Job, JobKind, JobStatus};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+struct JobEntry {
+    job: Job,
+    token: CancellationToken,
+    /// Changed since it was last taken by `take_changed`
+    changed: bool,
+}
+
+/// In memory registry of the jobs started by this replica.
+/// Finished jobs are kept for `retention_secs` so clients can poll their final status.
+/// The changed jobs are saved to the database by `sync_jobs` so any replica can report them.
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<Uuid, JobEntry>>,
+    retention_secs: i64,
+}
+
+impl JobRegistry {
+    pub fn new(retention_secs: u64) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            retention_secs: retention_secs as i64,
+        }
+    }
+
+    pub fn create(
+        &self,
+        kind: JobKind,
+        ingestion_id: &str,
+        files: &[String],
+    ) -> (Uuid, CancellationToken) {
+        let job = Job::new(kind, ingestion_id.to_owned(), files);
+        let id = job.id;
+        let token = CancellationToken::new();
+
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        jobs.insert(
+            id,
+            JobEntry {
+                job,
+                token: token.clone(),
+                changed: true,
+            },
+        );
+        info!("JobRegistry: {} Job {} created. Jobs: {}", kind, id, jobs.len());
+
+        (id, token)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(id).map(|e| e.job.clone())
+    }
+
+    /// Updates the status of a file, updates for finished jobs are ignored.
+    pub fn update_file<F>(&self, id: &Uuid, index: usize, f: F)
+    where
+        F: FnOnce(&mut FileStatus),
+    {
+        self.update(id, |job| {
+            if let Some(file) = job.files.get_mut(index) {
+                f(file);
+            }
+        });
+    }
+
+    /// Updates a job, updates for finished jobs are ignored.
+    pub fn update<F>(&self, id: &Uuid, f: F)
+    where
+        F: FnOnce(&mut Job),
+    {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(entry) = jobs.get_mut(id) {
+            if entry.job.is_finished() {
+                debug!("JobRegistry: Job {} finished, ignoring update", id);
+                return;
+            }
+            f(&mut entry.job);
+            entry.changed = true;
+        }
+    }
+
+    pub fn finish(&self, id: &Uuid) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let entry = jobs.get_mut(id)?;
+        entry.job.finish();
+        entry.changed = true;
+        info!("JobRegistry: Job {} finished: {}", id, entry.job.status);
+        Some(entry.job.clone())
+    }
+
+    /// Stops the job, it is `cancelling` until the writes in progress are finished
+    /// and the job calls `finish`.
+    pub fn cancel(&self, id: &Uuid) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let entry = jobs.get_mut(id)?;
+        if entry.job.status == JobStatus::Running {
+            entry.token.cancel();
+            entry.job.status = JobStatus::Cancelling;
+            entry.changed = true;
+            info!("JobRegistry: Job {} cancelling", id);
+        }
+        Some(entry.job.clone())
+    }
+
+    pub fn retention_secs(&self) -> i64 {
+        self.retention_secs
+    }
+
+    /// Jobs changed since the last call.
+    pub fn take_changed(&self) -> Vec<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.values_mut()
+            .filter(|e| e.changed)
+            .map(|e| {
+                e.changed = false;
+                e.job.clone()
+            })
+            .collect()
+    }
+
+    /// Marks the job as changed again, when it could not be saved.
+    pub fn touch(&self, id: &Uuid) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(entry) = jobs.get_mut(id) {
+            entry.changed = true;
+        }
+    }
+
+    /// Running job of the kind for the ingestion, if any.
+    pub fn find_running(&self, kind: JobKind, ingestion_id: &str) -> Option<Uuid> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .find(|e| {
+                e.job.kind == kind
+                    && e.job.ingestion_id == ingestion_id
+                    && !e.job.is_finished()
+            })
+            .map(|e| e.job.id)
+    }
+
+    pub fn running(&self) -> Vec<Uuid> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter()
+            .filter(|(_, e)| e.job.status == JobStatus::Running)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn prune(&self, jobs: &mut HashMap<Uuid, JobEntry>) {
+        let now = Utc::now();
+        jobs.retain(|_, e| match e.job.finished_at {
+            Some(t) => (now - t).num_seconds() < self.retention_secs,
+            None => true,
+        });
+    }
+}
@@ -0,0 +1,52 @@
+use crate::db::scylladb::ScyllaDbService;
+use crate::jobs::model::Job;
+use crate::jobs::registry::JobRegistry;
+use tracing::{debug, error, info};
+
+/// Saves the jobs changed since the last sync to the database, so the replicas that don't
+/// run them can report them, and cancels the running jobs whose cancel was requested to
+/// another replica.
+pub async fn sync_jobs(
+    registry: &JobRegistry,
+    db: &ScyllaDbService,
+) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    let changed = registry.take_changed();
+    if !changed.is_empty() {
+        debug!("sync_jobs: saving {} jobs", changed.len());
+    }
+    for job in changed {
+        let ttl = registry.retention_secs() as i32;
+        if let Err(e) = save_job(db, &job, ttl).await {
+            error!("sync_jobs: Error saving job {}: {:?}", job.id, e);
+            registry.touch(&job.id);
+        }
+    }
+
+    let running = registry.running();
+    if running.is_empty() {
+        return Ok(());
+    }
+    for id in db.get_cancel_requests(running).await? {
+        info!("sync_jobs: cancel of job {} requested to another replica", id);
+        registry.cancel(&id);
+    }
+    Ok(())
+}
+
+/// Saves the status of the job, it expires after `ttl` seconds.
+pub async fn save_job(
+    db: &ScyllaDbService,
+    job: &Job,
+    ttl: i32,
+) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    let status = serde_json::to_string(job)?;
+    db.save_job(
+        &job.id,
+        &job.ingestion_id,
+        &job.kind.to_string(),
+        &job.status.to_string(),
+        &status,
+        ttl,
+    )
+    .await
+}
@@ -1,32 +1,64 @@
 mod config;
 mod data;
 mod db;
+mod export;
+mod jobs;
 mod s3;
+mod search;
+mod source;
+mod stats;
+mod traversal;
 
 extern crate serde_json;
 extern crate num_cpus;
 
 use crate::config::Config;
-use crate::data::model::get_id_from_url;
-use crate::data::rest_api::TraversalNodeRequest;
-use crate::db::scylladb::ScyllaDbService;
-use crate::s3::s3::read_file;
-use actix_web::error::ErrorInternalServerError;
+use crate::data::model::{get_id_from_path, get_id_from_url};
+use crate::data::rest_api::{
+    AliasResponse, AncestorsRequest, AppError, BatchGetRequest, DeleteIngestionRequest,
+    ExportRequest, IngestionResponse, NodeResult, NodesPage, PageRequest, PathRequest,
+    RelationsPage, RelationsRequest, ResolvePathsRequest, SearchRequest, StatsRequest,
+    TagSearchRequest, TraversalFormat, TraversalNodeRequest, TypeCount,
+};
+use crate::db::scylladb::{ScyllaDbService, WritePolicy};
+use crate::export::source_json::{export, read_node, ExportOptions};
+use crate::jobs::model::{FileState, JobKind, JobStatus};
+use crate::jobs::registry::JobRegistry;
+use crate::jobs::sync::{self, sync_jobs};
+use crate::s3::s3::S3Reader;
+use crate::source::file::FileReader;
+use crate::source::http::HttpReader;
+use crate::search::prefix::search;
+use crate::source::SourceReaders;
+use crate::stats::subtree::compute_stats;
+use crate::traversal::ancestors::ancestors;
+use crate::traversal::engine::{traverse, TraversalLimits, TraversalOptions};
+use crate::traversal::path::{shortest_path, PathOptions};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
 use actix_web::middleware::Logger;
 use actix_web::web::Json;
-use actix_web::{get, post, web, web::Data, App, Error, HttpResponse, HttpServer};
+use actix_web::{delete, get, post, web, web::Data, App, Error, HttpResponse, HttpServer};
+use bytes::Bytes;
 use color_eyre::Result;
-use data::model::{Node, Relation, TraversalNode};
+use data::model::{Node, NodeStats, Relation};
 use data::rest_api::{GetNodeRequest, IngestionRequest};
-use data::source_model::{Relation as SourceRelation, Nodes};
-use db::model::DbNode;
-use futures::future::{BoxFuture, FutureExt};
-use std::collections::HashMap;
+use data::source_model::{
+    ColumnMapping, NodeRecord, Op, Relation as SourceRelation, SourceFormat, TableKind,
+};
+use data::source_parser::{parse, SourceItem};
+use db::error::{is_invalid_query, SaveNodesError};
+use db::model::{DbNode, DbRowKey, SaveReport};
+use futures::future::try_join_all;
+use std::collections::{HashMap, HashSet};
+use std::io::BufReader;
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, AcquireError, OwnedSemaphorePermit, Semaphore};
 use tokio::task;
 use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::SyncIoBridge;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
@@ -39,6 +71,7 @@ pub enum DIR {
     OUT
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Display, Debug)]
 enum REL {
     ISPARENT,
@@ -48,9 +81,33 @@ enum REL {
 struct AppState {
     db_svc: ScyllaDbService,
     semaphore: Arc<Semaphore>,
-    region: String
+    jobs: JobRegistry,
+    batch_size: usize,
+    failure_threshold: f64,
+    sources: SourceReaders,
+    traversal_limits: TraversalLimits
 }
 
+// parsed items buffered between the parser and the ingestion
+const PARSER_CHANNEL_SIZE: usize = 1024;
+// chunks of an export buffered between the reader and the response
+const EXPORT_CHANNEL_SIZE: usize = 16;
+// jobs are saved to the database and cancel requests are read at this interval
+const JOB_SYNC_INTERVAL_MS: u64 = 1000;
+// ids of a batch get
+const MAX_BATCH_GET_IDS: usize = 1000;
+// relations of a path
+const DEFAULT_PATH_MAX_DEPTH: usize = 10;
+// relations per page of the relations listing
+const DEFAULT_RELATIONS_PAGE_SIZE: usize = 100;
+const MAX_RELATIONS_PAGE_SIZE: usize = 1000;
+// nodes per page of a search or a listing by type
+const DEFAULT_SEARCH_PAGE_SIZE: usize = 100;
+const MAX_SEARCH_PAGE_SIZE: usize = 1000;
+// nodes of a type-ahead search
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 100;
+
 #[get("/node/{id}")]
 async fn get_by_id(
     path: web::Path<String>,
@@ -63,81 +120,498 @@ async fn get_by_id(
 
     let relations = query_data.get_relations.unwrap_or_default();
     let tags = query_data.get_tags.unwrap_or(true);
+    let stats = query_data.get_stats.unwrap_or(true);
 
-    let ret = get_node(&state.db_svc, &id, tags, relations).await?;
+    let ret = get_node(&state.db_svc, &id, tags, relations, stats).await?;
 
     let elapsed = now.elapsed();
     info!("get_by_id time: {:.2?}", elapsed);
     Ok(HttpResponse::Ok().json(ret))
 }
 
+#[get("/node/{id}/relations")]
+async fn get_relations(
+    path: web::Path<String>,
+    query_data: web::Query<RelationsRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let id = path.into_inner();
+    let request = query_data.into_inner();
+    info!("get_relations {}: {:?}", id, request);
+
+    if let Some(direction) = &request.direction {
+        if *direction != DIR::IN.to_string() && *direction != DIR::OUT.to_string() {
+            return Err(ErrorBadRequest(format!("Invalid direction {}", direction)));
+        }
+    }
+    let page_size = request
+        .page_size
+        .unwrap_or(DEFAULT_RELATIONS_PAGE_SIZE)
+        .clamp(1, MAX_RELATIONS_PAGE_SIZE);
+    let paging_state = decode_cursor(request.cursor)?;
+
+    let (rows, next) = state
+        .db_svc
+        .get_relations_page(
+            &id,
+            &request.direction,
+            &request.relation_type,
+            page_size as i32,
+            paging_state,
+        )
+        .await
+        .map_err(page_error)?;
+
+    let page = RelationsPage {
+        relations: rows.into_iter().map(Relation::from_db).collect(),
+        cursor: encode_cursor(next),
+    };
+
+    let elapsed = now.elapsed();
+    info!("get_relations time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(page))
+}
+
+// the cursor is the paging state of ScyllaDB, opaque for the clients
+fn decode_cursor(cursor: Option<String>) -> Result<Option<Bytes>, Error> {
+    cursor
+        .map(|c| base64::decode_config(c, base64::URL_SAFE_NO_PAD))
+        .transpose()
+        .map_err(|_| ErrorBadRequest("Invalid cursor"))
+        .map(|c| c.map(Bytes::from))
+}
+
+fn encode_cursor(paging_state: Option<Bytes>) -> Option<String> {
+    paging_state.map(|p| base64::encode_config(p, base64::URL_SAFE_NO_PAD))
+}
+
+/// A cursor that decodes but is not a paging state ScyllaDB accepts fails the request with a 400.
+fn page_error(error: Box<dyn std::error::Error + Sync + Send>) -> Error {
+    if is_invalid_query(error.as_ref()) {
+        return ErrorBadRequest("Invalid cursor");
+    }
+    ErrorInternalServerError(error)
+}
+
+#[get("/node/{id}/ancestors")]
+async fn get_ancestors(
+    path: web::Path<String>,
+    query_data: web::Query<AncestorsRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let id = path.into_inner();
+    info!("get_ancestors {}: {:?}", id, query_data);
+
+    let uuid = Uuid::parse_str(&id).map_err(ErrorBadRequest)?;
+    let ret = ancestors(
+        &state.db_svc,
+        uuid,
+        query_data.from_url.unwrap_or_default(),
+        query_data.get_tags.unwrap_or(true),
+        state.traversal_limits.max_nodes,
+    )
+    .await?;
+
+    let elapsed = now.elapsed();
+    info!("get_ancestors time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(ret))
+}
+
+#[post("/nodes:batchGet")]
+async fn batch_get(
+    payload: web::Json<BatchGetRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let request = payload.into_inner();
+    info!("batch_get {} ids", request.ids.len());
+    if request.ids.len() > MAX_BATCH_GET_IDS {
+        return Err(ErrorBadRequest(format!(
+            "Too many ids {}, the maximum is {}",
+            request.ids.len(),
+            MAX_BATCH_GET_IDS
+        )));
+    }
+
+    let relations = request.get_relations.unwrap_or_default();
+    let tags = request.get_tags.unwrap_or(true);
+    let stats = request.get_stats.unwrap_or(true);
+
+    let nodes: HashMap<String, Result<Option<Node>, String>> = state
+        .db_svc
+        .get_nodes(request.ids, tags, relations)
+        .await
+        .into_iter()
+        .map(|(id, result)| (id, result.map(Node::from)))
+        .collect();
+
+    let mut node_stats = HashMap::new();
+    if stats {
+        let ids = nodes.values().flatten().flatten().map(|n| n.uuid).collect();
+        node_stats = state.db_svc.get_nodes_stats(ids).await;
+    }
+
+    let ret: HashMap<String, NodeResult> = nodes
+        .into_iter()
+        .map(|(id, result)| {
+            let result = match result {
+                Ok(Some(mut node)) => match node_stats.remove(&node.uuid) {
+                    Some(Err(message)) => NodeResult::Error { message },
+                    found => {
+                        node.stats = found.and_then(Result::ok).flatten().map(NodeStats::from);
+                        NodeResult::Found { node: Box::new(node) }
+                    }
+                },
+                Ok(None) => NodeResult::NotFound,
+                Err(message) => NodeResult::Error { message },
+            };
+            (id, result)
+        })
+        .collect();
+
+    let elapsed = now.elapsed();
+    info!("batch_get time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(ret))
+}
+
 #[get("/traversal/{id}")]
 async fn traversal_by_id(
     path: web::Path<String>,
     query_data: web::Query<TraversalNodeRequest>,
     state: Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let now = Instant::now();
     let id = path.into_inner();
     info!("traversal_by_id: {}", id);
 
-    let result = traversal_recur(
-        state,
-        id,
-        Arc::new(query_data.direction.clone()),
-        Arc::new(query_data.relation_type.clone()),
-        0,
-        query_data.max_depth
-    )
-    .await;
+    traversal(state, id, query_data.into_inner()).await
+}
+
+async fn traversal(
+    state: Data<AppState>,
+    id: String,
+    query_data: TraversalNodeRequest,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let uuid = Uuid::parse_str(&id).map_err(ErrorBadRequest)?;
+
+    let options = TraversalOptions {
+        direction: query_data.direction,
+        relation_type: query_data.relation_type,
+        max_depth: query_data.max_depth,
+        limits: request_limits(state.traversal_limits, query_data.max_nodes, query_data.timeout_ms)?,
+        strict: query_data.strict.unwrap_or_default(),
+        tags: query_data.get_tags.unwrap_or_default(),
+    };
+    let result = traverse(&state.db_svc, uuid, &options).await?;
+
+    let response = match query_data.format {
+        // the root node alone, the shape of the response before the summary
+        TraversalFormat::Tree => HttpResponse::Ok().json(result.tree().root),
+        TraversalFormat::TreeSummary => HttpResponse::Ok().json(result.tree()),
+        TraversalFormat::Flat => HttpResponse::Ok().json(result.flat()),
+    };
 
     let elapsed = now.elapsed();
     info!("traversal time: {:.2?}", elapsed);
-    Ok(HttpResponse::Ok().json(result))
+    Ok(response)
 }
 
-fn traversal_recur<'a>(
+#[get("/path")]
+async fn shortest_path_between(
+    query_data: web::Query<PathRequest>,
     state: Data<AppState>,
-    id: String,
-    direction: Arc<String>,
-    relation_type: Arc<Option<String>>,
-    depth: usize,
-    max: usize,
-) -> BoxFuture<'a, Option<TraversalNode>> {
-    async move {
-        
-        let db_nodes = state
-            .db_svc
-            .get_node_traversal(&id, &direction, &relation_type)
-            .await
-            .ok()?;
-        let mut node = TraversalNode::from(db_nodes, depth)?;
-
-        if depth < max && node.relation_ids.len() > 0 {
-            let mut handlers: Vec<JoinHandle<_>> = Vec::new();
-           
-            for id in &node.relation_ids {
-
-                handlers.push(tokio::spawn(traversal_recur(
-                    state.clone(),
-                    id.to_string(),
-                    direction.clone(),
-                    relation_type.clone(),
-                    depth + 1,
-                    max,
-                )));
-            }
-      
-            for thread in handlers {
-                let child = thread.await.ok()?;
-                node.relations.push(child?);
-            }
-        }
-   
-        Some(node)
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let request = query_data.into_inner();
+    info!("shortest_path: {:?}", request);
+
+    let from = Uuid::parse_str(&request.from).map_err(ErrorBadRequest)?;
+    let to = Uuid::parse_str(&request.to).map_err(ErrorBadRequest)?;
+    let direction = request.direction.unwrap_or_else(|| DIR::OUT.to_string());
+    if direction != DIR::IN.to_string() && direction != DIR::OUT.to_string() {
+        return Err(ErrorBadRequest(format!("Invalid direction {}", direction)));
+    }
+
+    let options = PathOptions {
+        direction,
+        relation_type: request.relation_type,
+        max_depth: request.max_depth.unwrap_or(DEFAULT_PATH_MAX_DEPTH),
+        limits: request_limits(state.traversal_limits, request.max_nodes, request.timeout_ms)?,
+    };
+    let path = shortest_path(&state.db_svc, from, to, &options).await?;
+
+    let elapsed = now.elapsed();
+    info!("shortest_path time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(path))
+}
+
+/// Limits of a traversal request, the requests can only lower the limits of the service.
+/// A traversal that can't read any node is rejected.
+fn request_limits(
+    limits: TraversalLimits,
+    max_nodes: Option<usize>,
+    timeout_ms: Option<u64>,
+) -> Result<TraversalLimits, Error> {
+    if max_nodes == Some(0) {
+        return Err(ErrorBadRequest("max_nodes must be greater than 0"));
+    }
+    Ok(TraversalLimits {
+        max_nodes: max_nodes.unwrap_or(limits.max_nodes).min(limits.max_nodes),
+        timeout: timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(limits.timeout)
+            .min(limits.timeout),
+        concurrency: limits.concurrency,
+    })
+}
+
+/// Streams the subtree of the node as a source JSON file.
+#[get("/export/{id}")]
+async fn export_by_id(
+    path: web::Path<String>,
+    query_data: web::Query<ExportRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("export_by_id {}: {:?}", id, query_data);
+
+    let uuid = Uuid::parse_str(&id).map_err(ErrorBadRequest)?;
+    let rows = read_node(&state.db_svc, &uuid)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorNotFound(format!("Node {} not found", id)))?;
+
+    let options = ExportOptions {
+        max_depth: query_data.max_depth,
+        concurrency: state.traversal_limits.concurrency,
+    };
+    let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+    let state = state.clone();
+    task::spawn(async move {
+        let now = Instant::now();
+        export(&state.db_svc, rows, options, tx).await;
+        info!("export_by_id {} time: {:.2?}", uuid, now.elapsed());
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .streaming(ReceiverStream::new(rx)))
+}
+
+#[get("/ingestion/{ingestion_id}/path/{url:.*}")]
+async fn get_by_path(
+    path: web::Path<(String, String)>,
+    query_data: web::Query<GetNodeRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (ingestion_id, url) = path.into_inner();
+    info!("get_by_path {} {}, relations? {:?}", ingestion_id, url, query_data);
+
+    let id = get_id_from_path(&ingestion_id, &url).to_string();
+    let relations = query_data.get_relations.unwrap_or_default();
+    let tags = query_data.get_tags.unwrap_or(true);
+    let stats = query_data.get_stats.unwrap_or(true);
+
+    let ret = get_node(&state.db_svc, &id, tags, relations, stats).await?;
+    Ok(HttpResponse::Ok().json(ret))
+}
+
+/// Maps the paths to the ids of their nodes, the nodes are not read.
+#[post("/ingestion/{ingestion_id}/paths:resolve")]
+async fn resolve_paths(
+    path: web::Path<String>,
+    payload: web::Json<ResolvePathsRequest>,
+) -> HttpResponse {
+    let ingestion_id = path.into_inner();
+    let ids: HashMap<String, Uuid> = payload
+        .into_inner()
+        .paths
+        .into_iter()
+        .map(|p| {
+            let id = get_id_from_path(&ingestion_id, &p);
+            (p, id)
+        })
+        .collect();
+    HttpResponse::Ok().json(ids)
+}
+
+#[get("/ingestion/{ingestion_id}/types")]
+async fn get_types(path: web::Path<String>, state: Data<AppState>) -> Result<HttpResponse, Error> {
+    let ingestion_id = path.into_inner();
+    info!("get_types {}", ingestion_id);
+
+    let types: Vec<TypeCount> = state
+        .db_svc
+        .get_type_counts(&ingestion_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(|(node_type, nodes)| TypeCount { node_type, nodes })
+        .collect();
+    Ok(HttpResponse::Ok().json(types))
+}
+
+#[get("/ingestion/{ingestion_id}/types/{type}/nodes")]
+async fn get_type_nodes(
+    path: web::Path<(String, String)>,
+    query_data: web::Query<PageRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let (ingestion_id, node_type) = path.into_inner();
+    let request = query_data.into_inner();
+    info!("get_type_nodes {} {}: {:?}", ingestion_id, node_type, request);
+
+    let page_size = request
+        .page_size
+        .unwrap_or(DEFAULT_SEARCH_PAGE_SIZE)
+        .clamp(1, MAX_SEARCH_PAGE_SIZE);
+    let paging_state = decode_cursor(request.cursor)?;
+
+    let (rows, next) = state
+        .db_svc
+        .get_type_nodes_page(&ingestion_id, &node_type, page_size as i32, paging_state)
+        .await
+        .map_err(page_error)?;
+
+    let page = NodesPage {
+        nodes: rows.into_iter().filter_map(|r| Node::from(vec![r])).collect(),
+        cursor: encode_cursor(next),
+    };
+
+    let elapsed = now.elapsed();
+    info!("get_type_nodes time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[get("/search/tags")]
+async fn search_tags(
+    query_data: web::Query<TagSearchRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let request = query_data.into_inner();
+    info!("search_tags: {:?}", request);
+
+    let ingestion_id = search_ingestion(&state, request.ingestion_id, &request.alias).await?;
+    let page_size = request
+        .page_size
+        .unwrap_or(DEFAULT_SEARCH_PAGE_SIZE)
+        .clamp(1, MAX_SEARCH_PAGE_SIZE);
+    let paging_state = decode_cursor(request.cursor)?;
+
+    let (rows, next) = state
+        .db_svc
+        .search_tag_page(
+            &ingestion_id,
+            (&request.tag_type, &request.value),
+            &request.node_type,
+            page_size as i32,
+            paging_state,
+        )
+        .await
+        .map_err(page_error)?;
+
+    let page = NodesPage {
+        nodes: rows.into_iter().filter_map(|r| Node::from(vec![r])).collect(),
+        cursor: encode_cursor(next),
+    };
+
+    let elapsed = now.elapsed();
+    info!("search_tags time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[get("/search")]
+async fn search_nodes(
+    query_data: web::Query<SearchRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let now = Instant::now();
+    let request = query_data.into_inner();
+    info!("search_nodes: {:?}", request);
+
+    if request.q.trim().is_empty() {
+        return Err(ErrorBadRequest("The search query is empty"));
     }
-    .boxed()
-   
+    let ingestion_id = search_ingestion(&state, request.ingestion_id, &request.alias).await?;
+    let limit = request
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+
+    let nodes = search(&state.db_svc, &ingestion_id, &request.q, limit)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let elapsed = now.elapsed();
+    info!("search_nodes time: {:.2?}", elapsed);
+    Ok(HttpResponse::Ok().json(nodes))
+}
+
+/// Ingestion of a search, given by its id or by an alias.
+async fn search_ingestion(
+    state: &AppState,
+    ingestion_id: Option<String>,
+    alias: &Option<String>,
+) -> Result<String, Error> {
+    match (ingestion_id, alias) {
+        (Some(ingestion_id), None) => Ok(ingestion_id),
+        (None, Some(alias)) => resolve_alias(state, alias).await,
+        _ => Err(ErrorBadRequest("One of ingestion_id or alias is required")),
+    }
+}
+
+#[get("/alias/{alias}")]
+async fn get_alias(path: web::Path<String>, state: Data<AppState>) -> Result<HttpResponse, Error> {
+    let alias = path.into_inner();
+    let ingestion_id = resolve_alias(&state, &alias).await?;
+    Ok(HttpResponse::Ok().json(AliasResponse { alias, ingestion_id }))
+}
+
+#[get("/alias/{alias}/node/{url:.*}")]
+async fn get_by_alias(
+    path: web::Path<(String, String)>,
+    query_data: web::Query<GetNodeRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (alias, url) = path.into_inner();
+    info!("get_by_alias {} {}, relations? {:?}", alias, url, query_data);
+
+    let ingestion_id = resolve_alias(&state, &alias).await?;
+    let id = get_id_from_path(&ingestion_id, &url).to_string();
+    let relations = query_data.get_relations.unwrap_or_default();
+    let tags = query_data.get_tags.unwrap_or(true);
+    let stats = query_data.get_stats.unwrap_or(true);
+
+    let ret = get_node(&state.db_svc, &id, tags, relations, stats).await?;
+    Ok(HttpResponse::Ok().json(ret))
+}
+
+#[get("/alias/{alias}/traversal/{url:.*}")]
+async fn traversal_by_alias(
+    path: web::Path<(String, String)>,
+    query_data: web::Query<TraversalNodeRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (alias, url) = path.into_inner();
+    info!("traversal_by_alias: {} {}", alias, url);
+
+    let ingestion_id = resolve_alias(&state, &alias).await?;
+    let id = get_id_from_path(&ingestion_id, &url).to_string();
+    traversal(state, id, query_data.into_inner()).await
+}
+
+/// Gets the ingestion the alias points to, 404 if the alias does not exist.
+async fn resolve_alias(state: &AppState, alias: &str) -> Result<String, Error> {
+    state
+        .db_svc
+        .get_alias(alias)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorNotFound(format!("Alias {} not found", alias)))
 }
 
 async fn get_node(
@@ -145,13 +619,22 @@ async fn get_node(
     id: &str,
     tags: bool,
     relations: bool,
+    stats: bool,
 ) -> Result<Json<Option<Node>>, Error> {
     let db_nodes = db
         .get_node(id, tags, relations)
         .await
         .map_err(ErrorInternalServerError)?;
 
-    let node = Node::from(db_nodes);
+    let mut node = Node::from(db_nodes);
+
+    if let Some(node) = node.as_mut().filter(|_| stats) {
+        node.stats = db
+            .get_node_stats(&node.uuid)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .map(NodeStats::from);
+    }
 
     Ok(web::Json(node))
 }
@@ -162,217 +645,838 @@ async fn ingest(
     state: Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     info!("Ingest Request: {:?}", payload.files);
+    let mut request = payload.into_inner();
+    let wait = request.wait.unwrap_or_default();
+
+    request.files = state
+        .sources
+        .expand(&request.files)
+        .await
+        .map_err(ErrorBadRequest)?;
+    if !request.edges.is_empty() {
+        request.edges = state
+            .sources
+            .expand(&request.edges)
+            .await
+            .map_err(ErrorBadRequest)?;
+    }
+
+    if let Some(alias) = &request.alias {
+        let current = state
+            .db_svc
+            .get_alias(alias)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        if current.as_ref() == Some(&request.ingestion_id) {
+            return Err(ErrorBadRequest(format!(
+                "Alias {} already points to {}, use a new ingestion ID for the new version",
+                alias, request.ingestion_id
+            )));
+        }
+        // a version that is not complete is deleted, it can't hold the nodes of other loads
+        if state.jobs.find_running(JobKind::Ingestion, &request.ingestion_id).is_some()
+            || state
+                .db_svc
+                .has_ingestion_nodes(&request.ingestion_id)
+                .await
+                .map_err(ErrorInternalServerError)?
+        {
+            return Ok(HttpResponse::Conflict().json(AppError::new(format!(
+                "Ingestion {} already has nodes, use a new ingestion ID for the new version of {}",
+                request.ingestion_id, alias
+            ))));
+        }
+        // the checks above don't see a load of the same version started at the same time
+        if !state
+            .db_svc
+            .claim_version(&request.ingestion_id, alias)
+            .await
+            .map_err(ErrorInternalServerError)?
+        {
+            return Ok(HttpResponse::Conflict().json(AppError::new(format!(
+                "Ingestion {} is already loaded by another job, use a new ingestion ID for the new version of {}",
+                request.ingestion_id, alias
+            ))));
+        }
+    }
+
+    let files: Vec<String> = request.files.iter().chain(request.edges.iter()).cloned().collect();
+    let (job_id, token) = state.jobs.create(JobKind::Ingestion, &request.ingestion_id, &files);
+    let alias = request.alias.clone();
+    state.jobs.update(&job_id, |j| j.alias = alias);
+    save_job(&state, &job_id).await;
+    let handler = task::spawn(run_ingestion(job_id, request, state.clone(), token));
+
+    if wait {
+        debug!("Waiting for job {} to complete...", job_id);
+        handler.await.map_err(ErrorInternalServerError)?;
+        let job = state.jobs.get(&job_id);
+        return Ok(HttpResponse::Ok().json(job));
+    }
+
+    Ok(HttpResponse::Accepted().json(IngestionResponse {
+        job_id,
+        status: JobStatus::Running,
+    }))
+}
+
+/// Jobs run by this replica are read from memory, the others from the database.
+#[get("/ingest/jobs/{id}")]
+async fn get_job(path: web::Path<Uuid>, state: Data<AppState>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    if let Some(job) = state.jobs.get(&id) {
+        return Ok(HttpResponse::Ok().json(job));
+    }
+    saved_job(&state, &id).await
+}
+
+/// A job run by another replica is cancelled by that replica once it sees the request.
+#[delete("/ingest/jobs/{id}")]
+async fn cancel_job(path: web::Path<Uuid>, state: Data<AppState>) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Cancel Job Request: {}", id);
+    if let Some(job) = state.jobs.cancel(&id) {
+        return Ok(HttpResponse::Ok().json(job));
+    }
+
+    let ttl = state.jobs.retention_secs() as i32;
+    if !state
+        .db_svc
+        .request_cancel(&id, ttl)
+        .await
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(job_not_found(&id));
+    }
+    saved_job(&state, &id).await
+}
+
+async fn saved_job(state: &AppState, id: &Uuid) -> Result<HttpResponse, Error> {
+    match state.db_svc.get_job(id).await.map_err(ErrorInternalServerError)? {
+        Some(job) => Ok(HttpResponse::Ok().content_type("application/json").body(job)),
+        None => Ok(job_not_found(id)),
+    }
+}
+
+/// Saves the job right away, so it can be polled through any replica as soon as it is created.
+async fn save_job(state: &AppState, id: &Uuid) {
+    let job = match state.jobs.get(id) {
+        Some(job) => job,
+        None => return,
+    };
+    let ttl = state.jobs.retention_secs() as i32;
+    if let Err(e) = sync::save_job(&state.db_svc, &job, ttl).await {
+        // it is saved again by the next sync
+        error!("Error saving job {}: {:?}", id, e);
+    }
+}
+
+#[delete("/ingestion/{ingestion_id}")]
+async fn delete_ingestion(
+    path: web::Path<String>,
+    query_data: web::Query<DeleteIngestionRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let ingestion_id = path.into_inner();
+    info!("Delete Ingestion Request: {}", ingestion_id);
+
+    if let Some(conflict) = delete_conflict(&state, &ingestion_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+    {
+        return Ok(HttpResponse::Conflict().json(AppError::new(conflict)));
+    }
+
+    let (job_id, handler) = start_delete(&state, ingestion_id);
+    save_job(&state, &job_id).await;
+
+    if query_data.wait.unwrap_or_default() {
+        debug!("Waiting for job {} to complete...", job_id);
+        handler.await.map_err(ErrorInternalServerError)?;
+        let job = state.jobs.get(&job_id);
+        return Ok(HttpResponse::Ok().json(job));
+    }
+
+    Ok(HttpResponse::Accepted().json(IngestionResponse {
+        job_id,
+        status: JobStatus::Running,
+    }))
+}
+
+/// Why the ingestion can't be deleted: an alias points to it or an ingestion job of any replica
+/// is writing into it.
+async fn delete_conflict(
+    state: &AppState,
+    ingestion_id: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error + Sync + Send>> {
+    if let Some(alias) = state.db_svc.get_version_alias(ingestion_id).await? {
+        if state.db_svc.get_alias(&alias).await?.as_deref() == Some(ingestion_id) {
+            return Ok(Some(format!(
+                "Alias {} points to ingestion {}, switch it to another version first",
+                alias, ingestion_id
+            )));
+        }
+    }
+
+    let (ingestion, running, cancelling) = (
+        JobKind::Ingestion.to_string(),
+        JobStatus::Running.to_string(),
+        JobStatus::Cancelling.to_string(),
+    );
+    let writing = state
+        .db_svc
+        .get_ingestion_jobs(ingestion_id)
+        .await?
+        .into_iter()
+        .find(|(_, kind, status)| *kind == ingestion && (*status == running || *status == cancelling));
+    Ok(writing.map(|(id, _, _)| {
+        format!(
+            "Ingestion job {} is writing into ingestion {}, cancel it and wait until it is cancelled first",
+            id, ingestion_id
+        )
+    }))
+}
+
+/// Computes the statistics of an ingestion, for example after loading all its files with
+/// one job per file. Only one stats job per ingestion runs at a time on each replica.
+#[post("/ingestion/{ingestion_id}/stats")]
+async fn stats_ingestion(
+    path: web::Path<String>,
+    query_data: web::Query<StatsRequest>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let ingestion_id = path.into_inner();
+    info!("Stats Request: {}", ingestion_id);
+
+    if let Some(running) = state.jobs.find_running(JobKind::Stats, &ingestion_id) {
+        return Ok(HttpResponse::Conflict().json(AppError::new(format!(
+            "Job {} is already computing the statistics of {}",
+            running, ingestion_id
+        ))));
+    }
+
+    let (job_id, token) = state.jobs.create(JobKind::Stats, &ingestion_id, &[]);
+    save_job(&state, &job_id).await;
+    let handler = task::spawn(run_stats(job_id, ingestion_id, state.clone(), token));
+
+    if query_data.wait.unwrap_or_default() {
+        debug!("Waiting for job {} to complete...", job_id);
+        handler.await.map_err(ErrorInternalServerError)?;
+        let job = state.jobs.get(&job_id);
+        return Ok(HttpResponse::Ok().json(job));
+    }
+
+    Ok(HttpResponse::Accepted().json(IngestionResponse {
+        job_id,
+        status: JobStatus::Running,
+    }))
+}
+
+async fn run_stats(
+    job_id: Uuid,
+    ingestion_id: String,
+    state: Data<AppState>,
+    token: CancellationToken,
+) {
     let now = Instant::now();
+    update_stats(&job_id, &ingestion_id, &state, &token).await;
+    state.jobs.finish(&job_id);
+    let elapsed = now.elapsed();
+    info!("Stats Job {} Time: {:.2?}", job_id, elapsed);
+}
+
+fn start_delete(state: &Data<AppState>, ingestion_id: String) -> (Uuid, JoinHandle<()>) {
+    let (job_id, token) = state.jobs.create(JobKind::Delete, &ingestion_id, &[]);
+    let handler = task::spawn(run_delete(job_id, ingestion_id, state.clone(), token));
+    (job_id, handler)
+}
 
+fn job_not_found(id: &Uuid) -> HttpResponse {
+    HttpResponse::NotFound().json(AppError::new(format!("Job {} not found", id)))
+}
+
+async fn run_ingestion(
+    job_id: Uuid,
+    request: IngestionRequest,
+    state: Data<AppState>,
+    token: CancellationToken,
+) {
+    let now = Instant::now();
     let mut handlers: Vec<JoinHandle<_>> = Vec::new();
 
-    for file in payload.files.iter() {
-        let permit = state.semaphore.clone().acquire_owned().await;
+    let alias = request.alias;
+    let stats = request.stats.unwrap_or_default();
+    let ingestion_id = request.ingestion_id.clone();
+    let columns = Arc::new(request.columns);
+    let files = request
+        .files
+        .into_iter()
+        .map(|f| (f, TableKind::Nodes))
+        .chain(request.edges.into_iter().map(|f| (f, TableKind::Edges)));
+
+    for (index, (file, kind)) in files.enumerate() {
+        let permit = tokio::select! {
+            _ = token.cancelled() => break,
+            permit = state.semaphore.clone().acquire_owned() => permit,
+        };
+        let ingestion_id = request.ingestion_id.clone();
+        let options = FileOptions {
+            format: request.format.unwrap_or_else(|| SourceFormat::detect(&file)),
+            kind,
+            columns: columns.clone(),
+        };
+        let state = state.clone();
+        let file = file.to_string();
+        let token = token.clone();
+        // a cancelled file stops reading but finishes its writes, so nothing is written
+        // once the job is finished and the version can be deleted
         handlers.push(task::spawn(process_file(
-            payload.ingestion_id.clone(),
-            state.clone(),
-            file.to_string(),
+            job_id,
+            index,
+            ingestion_id,
+            state,
+            file,
+            options,
             permit,
+            token,
         )));
     }
 
     debug!("Waiting for files to be processed...");
-    for thread in handlers {
-        match thread.await {
-            Err(e) => return Err(ErrorInternalServerError(e)),
-            Ok(r) => {
-                if let Err(e) = r {
-                    error!("Error: {:?}", e);
-                    return Err(ErrorInternalServerError(e));
-                }
-            }
+    for (index, thread) in handlers.into_iter().enumerate() {
+        let error = match thread.await {
+            Err(e) => Some(e.to_string()),
+            Ok(Err(e)) => Some(e.to_string()),
+            Ok(Ok(())) => None,
+        };
+        if let Some(e) = error {
+            error!("Job {} Error: {:?}", job_id, e);
+            state.jobs.update_file(&job_id, index, |f| {
+                f.state = FileState::Failed;
+                f.error = Some(e);
+            });
+        }
+    }
+
+    let done = !token.is_cancelled()
+        && state
+            .jobs
+            .get(&job_id)
+            .map(|j| j.files.iter().all(|f| f.state == FileState::Done))
+            .unwrap_or_default();
+
+    // an incomplete version is deleted, its statistics are not needed
+    if stats && !token.is_cancelled() && (done || alias.is_none()) {
+        update_stats(&job_id, &ingestion_id, &state, &token).await;
+    }
+
+    if let Some(alias) = alias {
+        publish_version(&job_id, &alias, ingestion_id, done, &state).await;
+    }
+
+    state.jobs.finish(&job_id);
+    let elapsed = now.elapsed();
+    info!("Ingestion Job {} Time: {:.2?}", job_id, elapsed);
+}
+
+/// Computes the statistics of all the nodes of the ingestion, including the ones of previous
+/// ingestions with the same id, so they are right after a delta.
+async fn update_stats(
+    job_id: &Uuid,
+    ingestion_id: &str,
+    state: &AppState,
+    token: &CancellationToken,
+) {
+    // it doesn't take a file permit, it reads the manifest and writes the statistics without
+    // downloading anything and would otherwise delay the files of other jobs
+    match compute_stats(&state.db_svc, ingestion_id, token).await {
+        Ok(None) => {
+            info!("Job {} cancelled. Stopped computing statistics of {}", job_id, ingestion_id);
+        }
+        Ok(Some(nodes)) => state.jobs.update(job_id, |j| j.stats = Some(nodes)),
+        Err(e) => {
+            error!("Job {} Error computing statistics: {:?}", job_id, e);
+            state.jobs.update(job_id, |j| j.error = Some(e.to_string()));
+        }
+    }
+}
+
+/// Switches the alias to the new version if all its files are done and deletes the replaced
+/// version in the background. A version that is not complete is never visible, it is deleted.
+async fn publish_version(
+    job_id: &Uuid,
+    alias: &str,
+    ingestion_id: String,
+    done: bool,
+    state: &Data<AppState>,
+) {
+    let result = if done {
+        swap_alias(alias, &ingestion_id, state).await
+    } else {
+        info!("Version {} of {} is not complete, deleting it", ingestion_id, alias);
+        Ok(Some(ingestion_id))
+    };
+
+    match result {
+        Ok(Some(garbage)) => {
+            let (gc_job_id, _) = start_delete(state, garbage);
+            state.jobs.update(job_id, |j| j.gc_job_id = Some(gc_job_id));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("Job {} Error switching alias {}: {:?}", job_id, alias, e);
+            state.jobs.update(job_id, |j| j.error = Some(e.to_string()));
         }
     }
+}
+
+/// Returns the replaced version, if any.
+async fn swap_alias(
+    alias: &str,
+    ingestion_id: &str,
+    state: &AppState,
+) -> Result<Option<String>, Box<dyn std::error::Error + Sync + Send>> {
+    let previous = state.db_svc.get_alias(alias).await?;
+    if !state
+        .db_svc
+        .swap_alias(alias, previous.as_deref(), ingestion_id)
+        .await?
+    {
+        return Err(format!("Alias {} was changed by another ingestion", alias).into());
+    }
+    info!("Alias {} switched from {:?} to {}", alias, previous, ingestion_id);
+    Ok(previous.filter(|p| p != ingestion_id))
+}
+
+async fn run_delete(
+    job_id: Uuid,
+    ingestion_id: String,
+    state: Data<AppState>,
+    token: CancellationToken,
+) {
+    let now = Instant::now();
+    let result = delete_ingestion_nodes(&job_id, &ingestion_id, &state, &token).await;
 
+    if let Err(e) = result {
+        error!("Job {} Error: {:?}", job_id, e);
+        state.jobs.update(&job_id, |j| j.error = Some(e.to_string()));
+    }
+
+    state.jobs.finish(&job_id);
     let elapsed = now.elapsed();
-    info!("Ingestion Time: {:.2?}", elapsed);
-    Ok(HttpResponse::Ok().json(r#"{ "status": "OK"}"#))
+    info!("Delete Job {} Time: {:.2?}", job_id, elapsed);
 }
 
+/// Deletes the nodes of the ingestion page by page from its manifest,
+/// the manifest is removed once all the nodes are deleted.
+/// A cancelled delete stops after the page being deleted.
+async fn delete_ingestion_nodes(
+    job_id: &Uuid,
+    ingestion_id: &str,
+    state: &AppState,
+    token: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    let _permit = state.semaphore.acquire().await?;
+
+    let mut deleted = 0;
+    let mut paging_state = None;
+    loop {
+        if token.is_cancelled() {
+            info!("Job {} cancelled. Stopped deleting ingestion {}", job_id, ingestion_id);
+            return Ok(());
+        }
+        let (ids, next) = state
+            .db_svc
+            .get_ingestion_nodes(ingestion_id, paging_state)
+            .await?;
+        delete_node_indexes(state, &ids).await?;
+        deleted += state.db_svc.delete_nodes(ids).await?;
+        state.jobs.update(job_id, |j| j.deleted = Some(deleted));
+
+        paging_state = next;
+        if paging_state.is_none() {
+            break;
+        }
+    }
+
+    state.db_svc.delete_ingestion_manifest(ingestion_id).await?;
+    info!("Ingestion {} deleted. Nodes: {}", ingestion_id, deleted);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process_file(
+    job_id: Uuid,
+    index: usize,
     ingestion_id: String,
     state: Data<AppState>,
     file: String,
+    options: FileOptions,
     permit: Result<OwnedSemaphorePermit, AcquireError>,
+    token: CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
     info!(
-        "Processing File {} for provider {}. Reading file...",
-        file, ingestion_id
+        "Processing {:?} {:?} File {} for provider {}. Reading file...",
+        options.format, options.kind, file, ingestion_id
     );
     let now = Instant::now();
-    let contents = read_file(&state.region, file.to_string()).await?;
+    state
+        .jobs
+        .update_file(&job_id, index, |f| f.state = FileState::Reading);
+    let reader = tokio::select! {
+        _ = token.cancelled() => return Ok(()),
+        r = state.sources.open(&file) => r?,
+    };
 
-    info!("File Read. Processing Relations..");
-    let relations = process_relations(&ingestion_id, contents.relations);
-    info!(
-        "Relations processed, size: {}. Persisting Nodes..",
-        relations.len()
-    );
+    // the file is parsed while it is downloaded, nodes are persisted in batches
+    let (tx, mut rx) = mpsc::channel(PARSER_CHANNEL_SIZE);
+    let parser = task::spawn_blocking(move || {
+        let reader = BufReader::new(SyncIoBridge::new(reader));
+        parse(options.format, options.kind, &options.columns, reader, tx)
+    });
 
-    // Get all the nodes from the file in a flat structure and parent relations
-    let nodes = process_nodes(&ingestion_id, contents.nodes, relations).await?;
+    let mut progress = FileProgress::default();
+    let mut batch: Vec<DbNode> = Vec::new();
+    let mut updated: Vec<Uuid> = Vec::new();
+    loop {
+        // only the read is interrupted, a batch being persisted is always finished
+        let item = tokio::select! {
+            _ = token.cancelled() => {
+                info!("Job {} cancelled. Stopped processing file {}", job_id, file);
+                return Ok(());
+            }
+            item = rx.recv() => item,
+        };
+        let item = match item {
+            Some(item) => item,
+            None => break,
+        };
+        if batch.is_empty() {
+            state
+                .jobs
+                .update_file(&job_id, index, |f| f.state = FileState::Flattening);
+        }
+        let step = Instant::now();
+        let mut removals = Removals::default();
+        match item {
+            SourceItem::NodeRecord(record) => {
+                flatten_node_record(&ingestion_id, record, &mut batch, &mut removals);
+            }
+            SourceItem::Relation(relation) => {
+                progress.relations += 1;
+                let rows = process_relation(&ingestion_id, &relation)?;
+                match relation.op {
+                    Op::Removed => removals.rows.extend(rows.iter().map(DbRowKey::from)),
+                    Op::Added | Op::Updated => batch.extend(rows),
+                }
+            }
+        }
+        progress.flatten += step.elapsed();
+        updated.append(&mut removals.updated);
 
-    info!(
-        "Nodes processed, nodes size: {}.Persisting...",
-        nodes.len()
-    );
+        if !removals.is_empty() {
+            // upserts read before the removals are persisted first, so the delta is applied in order
+            if !batch.is_empty() {
+                persist_batch(&state, &job_id, index, std::mem::take(&mut batch), std::mem::take(&mut updated), &mut progress).await?;
+            }
+            progress.removed += apply_removals(&state, removals).await?;
+            let removed = progress.removed;
+            state.jobs.update_file(&job_id, index, |f| f.removed = removed);
+        }
 
-    // persist in DB and wait
-    state.db_svc.save_nodes(nodes).await?;
+        if batch.len() >= state.batch_size {
+            persist_batch(&state, &job_id, index, std::mem::take(&mut batch), std::mem::take(&mut updated), &mut progress).await?;
+        }
+    }
+
+    parser.await??;
+    let read_ms = now.elapsed().as_millis() as u64;
+    info!("File Read. Relations: {}, Rows: {}", progress.relations, progress.report.rows + batch.len());
+
+    if !batch.is_empty() {
+        persist_batch(&state, &job_id, index, batch, updated, &mut progress).await?;
+    }
 
     info!("Nodes Persisted!");
     let elapsed = now.elapsed();
     info!("File {} processed. Took {:.2?}", file, elapsed);
+    state.jobs.update_file(&job_id, index, |f| {
+        f.state = FileState::Done;
+        f.read_ms = Some(read_ms);
+        f.duration_ms = Some(elapsed.as_millis() as u64);
+    });
 
     let _permit = permit;
 
     Ok(())
 }
 
+/// How a file is parsed, `kind` and `columns` are only used by the table formats
+struct FileOptions {
+    format: SourceFormat,
+    kind: TableKind,
+    columns: Arc<ColumnMapping>,
+}
 
-fn process_relations(
-    ingestion_id: &str,
-    relations: Vec<SourceRelation>,
-) -> HashMap<String, Vec<Relation>> {
-    let now = Instant::now();
-    let ret = relations.iter().fold(HashMap::new(), move |mut acc, r| {
-        let source = get_path(&r.source);
-        let target = get_path(&r.target);
+#[derive(Default)]
+struct FileProgress {
+    relations: usize,
+    removed: usize,
+    flatten: Duration,
+    persist: Duration,
+    report: SaveReport,
+}
 
-        let rel = Relation::new(
-            ingestion_id.to_owned(),
-            r.type_field.clone(),
-            target.clone(),
-            true,
-        );
-        acc.entry(source.clone()).or_insert_with(Vec::new).push(rel);
-        // the other side
-        let rel_target = Relation::new(ingestion_id.to_owned(), r.type_field.clone(), source, false);
-        acc.entry(target).or_insert_with(Vec::new).push(rel_target);
-        acc
+/// Persists the rows of the batch. The `updated` nodes are removed from the indexes with
+/// their previous type and tags first, the new ones are indexed by the batch.
+async fn persist_batch(
+    state: &AppState,
+    job_id: &Uuid,
+    index: usize,
+    batch: Vec<DbNode>,
+    updated: Vec<Uuid>,
+    progress: &mut FileProgress,
+) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    debug!("Persisting batch of {} rows", batch.len());
+    state
+        .jobs
+        .update_file(job_id, index, |f| f.state = FileState::Persisting);
+
+    let step = Instant::now();
+    if !updated.is_empty() {
+        delete_node_indexes(state, &updated).await?;
+    }
+    let report = state.db_svc.save_nodes(batch).await;
+    progress.persist += step.elapsed();
+    progress.report.merge(report);
+
+    let report = progress.report.clone();
+    let (relations, flatten, persist) = (progress.relations, progress.flatten, progress.persist);
+    state.jobs.update_file(job_id, index, |f| {
+        f.relations = relations;
+        f.rows = report.rows;
+        f.flatten_ms = Some(flatten.as_millis() as u64);
+        f.persist_ms = Some(persist.as_millis() as u64);
+        f.report = Some(report);
     });
-    let elapsed = now.elapsed();
-    info!("process_relations Took {:.2?}", elapsed);
-    ret
+
+    // the ratio is of all the rows of the file persisted so far, not only the ones of the batch
+    if progress.report.exceeds(state.failure_threshold) {
+        return Err(SaveNodesError::FailureThreshold(progress.report.clone()).into());
+    }
+    Ok(())
 }
 
-fn get_path(path: &[String]) -> String {
-    path.iter()
-        .map(|r| r.clone())
-        .reduce(|a, b| format!("{}/{}", &a, &b))
-        .unwrap()
-}
-
-async fn process_nodes(
-    ingestion_id: &String,
-    nodes: Vec<Nodes>,
-    relations: HashMap<String, Vec<Relation>>,
-) -> Result<Vec<DbNode>> {
-    info!("process_nodes: {}", ingestion_id);
-    let now = Instant::now();
-    let mut db_nodes: Vec<DbNode> = Vec::new();
-    let parent = None;
-    let path: String = String::new();
-    flatten_nodes(
-        ingestion_id,
-        &nodes,
-        &path,
-        &parent,
-        &mut db_nodes,
-        &relations,
-    );
-    let elapsed = now.elapsed();
-    info!("process_nodes Took {:.2?}", elapsed);
-    Ok(db_nodes)
+/// Nodes and relation rows removed by a delta
+#[derive(Default)]
+struct Removals {
+    nodes: Vec<Uuid>,
+    rows: Vec<DbRowKey>,
+    /// Updated nodes, their previous type and tags are removed from the indexes
+    /// before they are persisted
+    updated: Vec<Uuid>,
 }
 
-fn flatten_nodes(
-    ingestion_id: &String,
-    nodes: &Vec<Nodes>,
-    path: &String,
-    parent: &Option<(Uuid, String)>,
-    db_nodes: &mut Vec<DbNode>,
-    relations: &HashMap<String, Vec<Relation>>,
-) {
-    debug!(
-        "Flattening Nodes, path {}, node size {}",
-        path,
-        db_nodes.len()
+impl Removals {
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.rows.is_empty()
+    }
+}
+
+/// Deletes the relation rows and the nodes. The stored descendants of the nodes are deleted
+/// with them, following their ISCHILD rows level by level, also the ones not listed in the delta.
+/// For every node the other side of its relations, like the ISCHILD row of its parent or the
+/// ISPARENT rows of its children, is also deleted.
+/// Returns the number of nodes and relations removed.
+async fn apply_removals(
+    state: &AppState,
+    mut removals: Removals,
+) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+    let relations = removals.rows.len() / 2;
+
+    let mut seen: HashSet<Uuid> = removals.nodes.iter().copied().collect();
+    let mut level = removals.nodes.clone();
+    let mut rows: Vec<DbNode> = vec![];
+    while !level.is_empty() {
+        let level_rows: Vec<DbNode> = try_join_all(
+            level.iter().map(|id| state.db_svc.get_node_rows(id)),
+        )
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+        level = stored_children(&level_rows)
+            .into_iter()
+            .filter(|id| seen.insert(*id))
+            .collect();
+        removals.nodes.extend(&level);
+        rows.extend(level_rows);
+    }
+    let nodes = removals.nodes.len();
+
+    removals.rows.extend(rows.iter().filter_map(reverse_row));
+    state.db_svc.delete_node_indexes(&rows).await?;
+
+    debug!("Removing {} nodes and {} rows", nodes, removals.rows.len());
+    state.db_svc.delete_rows(removals.rows).await?;
+    state.db_svc.delete_nodes(removals.nodes).await?;
+    Ok(nodes + relations)
+}
+
+/// Removes the nodes from the type and tag indexes, reading the type and tags they have
+/// in the database.
+async fn delete_node_indexes(
+    state: &AppState,
+    ids: &[Uuid],
+) -> Result<usize, Box<dyn std::error::Error + Sync + Send>> {
+    let keys = ids.iter().map(|id| id.to_string()).collect();
+    let mut roots = vec![];
+    for (_, result) in state.db_svc.get_nodes(keys, true, false).await {
+        roots.extend(result?);
+    }
+    state.db_svc.delete_node_indexes(&roots).await
+}
+
+/// Ids of the children of the OUT ISCHILD rows.
+fn stored_children(rows: &[DbNode]) -> Vec<Uuid> {
+    let (out, child) = (DIR::OUT.to_string(), REL::ISCHILD.to_string());
+    rows.iter()
+        .filter(|r| r.direction.as_ref() == Some(&out) && r.relation.as_ref() == Some(&child))
+        .filter_map(|r| Uuid::parse_str(r.relates_to.as_deref()?).ok())
+        .collect()
+}
+
+/// Key of the other side of a relation row, None for the root row.
+fn reverse_row(row: &DbNode) -> Option<DbRowKey> {
+    let direction = row.direction.as_deref().filter(|d| !d.is_empty())?;
+    let relation = row.relation.clone()?;
+    let relates_to = Uuid::parse_str(row.relates_to.as_deref()?).ok()?;
+
+    let out = direction == DIR::OUT.to_string();
+
+    Some(DbRowKey {
+        uuid: relates_to,
+        direction: if out { DIR::IN } else { DIR::OUT }.to_string(),
+        relation: reverse_relation(relation),
+        relates_to: row.uuid.to_string(),
+    })
+}
+
+/// Type of the other side of a relation, ISCHILD and ISPARENT are the two sides
+/// of the hierarchy, the custom relations have the same type on both sides.
+fn reverse_relation(relation: String) -> String {
+    if relation == REL::ISCHILD.to_string() {
+        REL::ISPARENT.to_string()
+    } else if relation == REL::ISPARENT.to_string() {
+        REL::ISCHILD.to_string()
+    } else {
+        relation
+    }
+}
+
+/// Both sides of a relation, OUT from the source and IN from the target.
+/// A relation without source or target fails the file like a parse error.
+fn process_relation(
+    ingestion_id: &str,
+    relation: &SourceRelation,
+) -> Result<Vec<DbNode>, Box<dyn std::error::Error + Sync + Send>> {
+    if relation.source.is_empty() || relation.target.is_empty() {
+        return Err(format!(
+            "Empty relation path, source {:?} target {:?}",
+            relation.source, relation.target
+        )
+        .into());
+    }
+    let source = get_path(&relation.source);
+    let target = get_path(&relation.target);
+    let tags: Vec<(String, String)> = relation
+        .tags
+        .iter()
+        .flatten()
+        .map(|t| (t.type_field.clone(), t.value.clone()))
+        .collect();
+
+    let rel = Relation::new(
+        ingestion_id.to_owned(),
+        relation.type_field.clone(),
+        target.clone(),
+        true,
+        tags.clone(),
     );
-    for node in nodes {
-        let empty = &mut Vec::new();
-        let tags = node.tags.as_ref().get_or_insert(empty).clone();
+    let source_id = get_id_from_url(ingestion_id.to_owned(), source.clone());
+    // the other side
+    let rel_target = Relation::new(ingestion_id.to_owned(), relation.type_field.clone(), source, false, tags);
+    let target_id = get_id_from_url(ingestion_id.to_owned(), target);
 
-        let mut parent_url = path.to_owned();
-        let url = path.clone() + node.name.as_str();
+    Ok(vec![
+        DbNode::from_rel(source_id, ingestion_id.to_owned(), &rel),
+        DbNode::from_rel(target_id, ingestion_id.to_owned(), &rel_target),
+    ])
+}
 
-        parent_url.pop();
+fn get_path(path: &[String]) -> String {
+    path.join("/")
+}
 
-        let root = DbNode::root(
-            ingestion_id.clone(),
-            url.clone(),
-            node.name.clone(),
-            node.type_field.clone(),
-            tags.to_vec(),
-        );
+/// Flattens a node record: its root row and, unless it is a root, the ISCHILD row of its
+/// parent and its own ISPARENT row. The children are records of their own, read before it.
+/// A removed node has no rows, it is removed with its stored descendants.
+fn flatten_node_record(
+    ingestion_id: &str,
+    record: NodeRecord,
+    db_nodes: &mut Vec<DbNode>,
+    removals: &mut Removals,
+) {
+    let url = match record.parent.is_empty() {
+        true => record.name.clone(),
+        false => format!("{}/{}", get_path(&record.parent), record.name),
+    };
 
-        let id = root.uuid;
-        let name = root.name.clone();
-     
-        db_nodes.push(root);
-
-        if parent.is_some() {
-            let (parent_id, parent_name) = parent.as_ref().unwrap();
-
-            let rel = DbNode::relation(
-                id,
-                ingestion_id.clone(),
-                DIR::IN.to_string(),
-                REL::ISPARENT.to_string(),
-                parent_id.to_string(),
-                parent_name.to_owned(),
-            );
-            db_nodes.push(rel);
-        }
+    if record.op == Op::Removed {
+        removals
+            .nodes
+            .push(get_id_from_url(ingestion_id.to_owned(), url));
+        return;
+    }
 
-        let empty_rel = &mut Vec::new();
-        for r in relations.get(&url).get_or_insert(empty_rel).iter() {
-            db_nodes.push(DbNode::from_rel(id, ingestion_id.clone(), r));
-        }
+    let root = DbNode::root(
+        ingestion_id.to_owned(),
+        url,
+        record.name.clone(),
+        record.type_field,
+        record.tags.unwrap_or_default(),
+    );
+    let id = root.uuid;
+    if record.op == Op::Updated {
+        removals.updated.push(id);
+    }
 
-        for c in &node.children {
-            let child_url = url.clone() + "/" + c.name.as_str();
-            let child_id = get_id_from_url(ingestion_id.clone(), child_url);
-            let rel = DbNode::relation(
-                id,
-                ingestion_id.clone(),
-                DIR::OUT.to_string(),
-                REL::ISCHILD.to_string(),
-                child_id.to_string(),
-                c.name.clone(),
-            );
-            db_nodes.push(rel);
-        }
+    let parent = record.parent.last().map(|parent_name| {
+        let parent_id = get_id_from_url(ingestion_id.to_owned(), get_path(&record.parent));
+        (parent_id, parent_name.clone())
+    });
+    if let Some((parent_id, _)) = &parent {
+        db_nodes.push(DbNode::relation(
+            *parent_id,
+            ingestion_id.to_owned(),
+            DIR::OUT.to_string(),
+            REL::ISCHILD.to_string(),
+            id.to_string(),
+            record.name,
+        ));
+    }
 
-        if !node.children.is_empty() {
-            let mut new_path = path.clone();
-            new_path.push_str(node.name.as_str());
-            new_path.push('/');
-            let parent = Some((id, name));
-            flatten_nodes(
-                ingestion_id,
-                &node.children,
-                &new_path,
-                &parent,
-                db_nodes,
-                relations,
-            )
-        }
+    db_nodes.push(root);
+
+    if let Some((parent_id, parent_name)) = parent {
+        db_nodes.push(DbNode::relation(
+            id,
+            ingestion_id.to_owned(),
+            DIR::IN.to_string(),
+            REL::ISPARENT.to_string(),
+            parent_id.to_string(),
+            parent_name,
+        ));
     }
 }
 
@@ -392,14 +1496,47 @@ async fn main() -> Result<()> {
         num_cpus, parallel_files, db_parallelism, region
     );
 
+    let write_policy = WritePolicy {
+        retries: config.db_retries,
+        backoff_ms: config.db_retry_backoff_ms,
+    };
+
     let db = ScyllaDbService::new(config.db_dc, config.db_url, 
-        db_parallelism, config.schema_file).await;
+        db_parallelism, write_policy, config.schema_file).await;
+
+    let file_reader = config
+        .local_source_dir
+        .map(|dir| FileReader::new(&dir).expect("Local source dir"));
+    let sources = SourceReaders::new(
+        S3Reader::new(&region, config.s3_endpoint).expect("S3 configuration"),
+        file_reader,
+        HttpReader::new(),
+    );
 
     let sem = Arc::new(Semaphore::new(parallel_files));
     let data = Data::new(AppState {
         db_svc: db,
         semaphore: sem,
-        region
+        jobs: JobRegistry::new(config.job_retention_secs),
+        batch_size: config.ingestion_batch_size,
+        failure_threshold: config.db_failure_threshold,
+        sources,
+        traversal_limits: TraversalLimits {
+            max_nodes: config.traversal_max_nodes,
+            timeout: Duration::from_millis(config.traversal_timeout_ms),
+            concurrency: config.traversal_concurrency,
+        }
+    });
+
+    let jobs = data.clone();
+    task::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(JOB_SYNC_INTERVAL_MS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = sync_jobs(&jobs.jobs, &jobs.db_svc).await {
+                error!("Error syncing jobs: {:?}", e);
+            }
+        }
     });
 
     info!("Starting server at http://{}:{}/", host, port);
@@ -408,8 +1545,26 @@ async fn main() -> Result<()> {
             .wrap(Logger::default())
             .app_data(data.clone())
             .service(ingest)
+            .service(get_job)
+            .service(cancel_job)
+            .service(delete_ingestion)
+            .service(stats_ingestion)
             .service(get_by_id)
+            .service(batch_get)
+            .service(get_relations)
+            .service(get_ancestors)
             .service(traversal_by_id)
+            .service(shortest_path_between)
+            .service(export_by_id)
+            .service(get_by_path)
+            .service(resolve_paths)
+            .service(get_types)
+            .service(get_type_nodes)
+            .service(search_tags)
+            .service(search_nodes)
+            .service(get_alias)
+            .service(get_by_alias)
+            .service(traversal_by_alias)
     })
     .bind(format!("{}:{}", host, port))?
     .workers(num_cpus * 2)
@@ -418,3 +1573,200 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scylla::transport::errors::{DbError, QueryError};
+
+    fn source_relation(source: &[&str], target: &[&str]) -> SourceRelation {
+        SourceRelation {
+            type_field: "custom".to_owned(),
+            source: source.iter().map(|p| p.to_string()).collect(),
+            target: target.iter().map(|p| p.to_string()).collect(),
+            tags: None,
+            op: Op::Added,
+        }
+    }
+
+    #[test]
+    fn process_relation_writes_both_sides() {
+        let rows = process_relation("test", &source_relation(&["a", "b"], &["c"])).unwrap();
+        let source_id = get_id_from_url("test".to_owned(), "a/b".to_owned());
+        let target_id = get_id_from_url("test".to_owned(), "c".to_owned());
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].uuid, source_id);
+        assert_eq!(rows[0].direction.as_deref(), Some("OUT"));
+        assert_eq!(rows[0].relation.as_deref(), Some("custom"));
+        assert_eq!(rows[0].relates_to, Some(target_id.to_string()));
+        assert_eq!(rows[1].uuid, target_id);
+        assert_eq!(rows[1].direction.as_deref(), Some("IN"));
+        assert_eq!(rows[1].relates_to, Some(source_id.to_string()));
+    }
+
+    fn id(url: &str) -> Uuid {
+        get_id_from_url("test".to_owned(), url.to_owned())
+    }
+
+    #[test]
+    fn reverse_row_of_the_hierarchy() {
+        let (parent, child) = (id("a"), id("a/b"));
+        let row = DbNode::relation(
+            parent,
+            "test".to_owned(),
+            DIR::OUT.to_string(),
+            REL::ISCHILD.to_string(),
+            child.to_string(),
+            "b".to_owned(),
+        );
+        let key = reverse_row(&row).unwrap();
+
+        assert_eq!(key.uuid, child);
+        assert_eq!(key.direction, "IN");
+        assert_eq!(key.relation, "ISPARENT");
+        assert_eq!(key.relates_to, parent.to_string());
+    }
+
+    #[test]
+    fn reverse_row_of_a_custom_relation() {
+        let rows = process_relation("test", &source_relation(&["a"], &["c"])).unwrap();
+        let key = reverse_row(&rows[1]).unwrap();
+
+        // the other side of the IN row of the target is the OUT row of the source
+        assert_eq!(key.uuid, rows[0].uuid);
+        assert_eq!(key.direction, "OUT");
+        assert_eq!(key.relation, "custom");
+        assert_eq!(key.relates_to, id("c").to_string());
+    }
+
+    #[test]
+    fn reverse_row_skips_roots_and_invalid_ids() {
+        let root = DbNode::root("test".to_owned(), "a".to_owned(), "a".to_owned(), "t".to_owned(), vec![]);
+        assert!(reverse_row(&root).is_none());
+
+        let row = DbNode::relation(
+            id("a"),
+            "test".to_owned(),
+            DIR::OUT.to_string(),
+            "custom".to_owned(),
+            "not-an-id".to_owned(),
+            "b".to_owned(),
+        );
+        assert!(reverse_row(&row).is_none());
+    }
+
+    #[test]
+    fn reverse_relation_swaps_the_hierarchy() {
+        assert_eq!(reverse_relation("ISCHILD".to_owned()), "ISPARENT");
+        assert_eq!(reverse_relation("ISPARENT".to_owned()), "ISCHILD");
+        assert_eq!(reverse_relation("custom".to_owned()), "custom");
+    }
+
+    #[test]
+    fn stored_children_follow_the_ischild_rows() {
+        let relation = |direction: DIR, relation: REL, to: &str| {
+            DbNode::relation(
+                id("a/b"),
+                "test".to_owned(),
+                direction.to_string(),
+                relation.to_string(),
+                to.to_owned(),
+                "c".to_owned(),
+            )
+        };
+        let rows = vec![
+            DbNode::root("test".to_owned(), "a/b".to_owned(), "b".to_owned(), "t".to_owned(), vec![]),
+            relation(DIR::IN, REL::ISPARENT, &id("a").to_string()),
+            // a child that is not in the delta, only stored
+            relation(DIR::OUT, REL::ISCHILD, &id("a/b/c").to_string()),
+            relation(DIR::IN, REL::ISCHILD, &id("a/b/d").to_string()),
+            relation(DIR::OUT, REL::ISCHILD, "not-an-id"),
+        ];
+
+        assert_eq!(stored_children(&rows), vec![id("a/b/c")]);
+    }
+
+    #[test]
+    fn flatten_node_record_of_a_delta() {
+        let record = |name: &str, op: Op| NodeRecord {
+            name: name.to_owned(),
+            type_field: "t".to_owned(),
+            parent: vec!["a".to_owned()],
+            tags: None,
+            op,
+        };
+        let mut batch = vec![];
+        let mut removals = Removals::default();
+
+        flatten_node_record("test", record("b", Op::Removed), &mut batch, &mut removals);
+        assert!(batch.is_empty());
+        assert_eq!(removals.nodes, vec![id("a/b")]);
+
+        flatten_node_record("test", record("c", Op::Updated), &mut batch, &mut removals);
+        assert_eq!(removals.updated, vec![id("a/c")]);
+        // the ISCHILD row of the parent, the root row and the ISPARENT row of the node
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch[0].uuid, id("a"));
+        assert_eq!(batch[0].relates_to, Some(id("a/c").to_string()));
+        assert!(batch[1].is_root());
+        assert_eq!(batch[2].relation.as_deref(), Some("ISPARENT"));
+
+        // a root only has its root row
+        let mut root = record("a", Op::Added);
+        root.parent.clear();
+        batch.clear();
+        flatten_node_record("test", root, &mut batch, &mut removals);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].uuid, id("a"));
+        assert!(batch[0].is_root());
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let paging_state = Bytes::from_static(&[0, 8, 0, 0, 0, 255, 1, 2]);
+        let cursor = encode_cursor(Some(paging_state.clone()));
+
+        assert!(cursor.as_deref().unwrap().chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(decode_cursor(cursor).unwrap(), Some(paging_state));
+        assert_eq!(encode_cursor(None), None);
+        assert_eq!(decode_cursor(None).unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_cursors_are_bad_requests() {
+        let status = |e: Error| e.as_response_error().status_code();
+
+        let error = decode_cursor(Some("not a cursor!".to_owned())).unwrap_err();
+        assert_eq!(status(error), http::StatusCode::BAD_REQUEST);
+
+        // a tampered cursor decodes, ScyllaDB rejects its paging state
+        let rejected = QueryError::DbError(DbError::ProtocolError, "Invalid paging state".to_owned());
+        assert_eq!(status(page_error(Box::new(rejected))), http::StatusCode::BAD_REQUEST);
+
+        let timeout = QueryError::TimeoutError;
+        assert_eq!(status(page_error(Box::new(timeout))), http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn request_limits_can_only_lower_the_limits() {
+        let limits = TraversalLimits {
+            max_nodes: 100,
+            timeout: Duration::from_millis(1000),
+            concurrency: 8,
+        };
+        let lowered = request_limits(limits, Some(10), Some(5000)).unwrap();
+        assert_eq!(lowered.max_nodes, 10);
+        assert_eq!(lowered.timeout, Duration::from_millis(1000));
+
+        let raised = request_limits(limits, Some(1000), None).unwrap();
+        assert_eq!(raised.max_nodes, 100);
+        assert!(request_limits(limits, Some(0), None).is_err());
+    }
+
+    #[test]
+    fn process_relation_fails_on_empty_paths() {
+        assert!(process_relation("test", &source_relation(&[], &["c"])).is_err());
+        assert!(process_relation("test", &source_relation(&["a"], &[])).is_err());
+    }
+}
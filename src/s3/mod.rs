@@ -1 +1,2 @@
+#[allow(clippy::module_inception)]
 pub mod s3;
\ No newline at end of file
@@ -1,48 +1,174 @@
 extern crate s3;
-use color_eyre::Result;
+use crate::source::{file_or_directory, Expansion, SourceError, SourceObject, SourceReader};
+use futures::future::{BoxFuture, FutureExt};
 use s3::bucket::Bucket;
 use s3::creds::Credentials;
+use s3::error::S3Error;
 use s3::Region;
-use std::{error::Error, time::Instant};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, DuplexStream, ReadBuf};
+use tokio::task::JoinHandle;
 use url::Url;
-use crate::data::source_model::File;
-use std::time::Duration;
 use tracing::{info, debug};
 
-pub async fn read_file(region: &str, file: String) -> Result<File, Box<dyn Error + Sync + Send>> {
-    info!("Reading file: {}", file);
-    let now = Instant::now();
-    let credentials = Credentials::from_env()?;
+// bytes buffered between the download and the parser
+const STREAM_BUFFER_SIZE: usize = 8 * 1024 * 1024;
 
-    debug!("Credentials: {:?}", credentials);
+/// Reads `s3://bucket/key` URLs from AWS S3 or from a S3 compatible server, like MinIO or localstack,
+/// when a custom endpoint is set.
+pub struct S3Reader {
+    region: Region,
+    path_style: bool,
+}
+
+impl S3Reader {
+    pub fn new(region: &str, endpoint: Option<String>) -> Result<Self, SourceError> {
+        let reader = match endpoint {
+            Some(endpoint) => S3Reader {
+                region: Region::Custom {
+                    region: region.to_owned(),
+                    endpoint,
+                },
+                path_style: true,
+            },
+            None => S3Reader {
+                region: region.parse()?,
+                path_style: false,
+            },
+        };
+        info!("S3Reader: Region {:?}", reader.region);
+        Ok(reader)
+    }
+
+    fn bucket(&self, file_struct: &Url) -> Result<Bucket, SourceError> {
+        let credentials = Credentials::from_env()?;
+
+        debug!("Credentials: {:?}", credentials);
+
+        let bucket = file_struct.host_str().ok_or("Missing bucket")?;
+        debug!("Bucket {}, Region {:?}", bucket, self.region);
+
+        let bucket = Bucket::new(bucket, self.region.clone(), credentials)?;
+        if self.path_style {
+            return Ok(bucket.with_path_style());
+        }
+        Ok(bucket)
+    }
+
+    async fn read_file_stream(&self, mut bucket: Bucket, file_struct: &Url) -> Result<DownloadStream, SourceError> {
+        info!("Reading file: {}", file_struct);
+        let now = Instant::now();
 
-    let region: Region =  region.parse()?;
+        // the download is throttled by the ingestion, big files take longer than any sensible timeout
+        bucket.set_request_timeout(None);
 
-    let file_struct: Url = Url::parse(&file)?;
 
-    debug!("file structure: {:?}", file_struct);
+        let path = file_struct.path().to_owned();
+        debug!("path: {:?}", path);
 
-    let bucket = file_struct.host_str().unwrap();
-    debug!("Bucket {}, Region {:?}", bucket, region);
+        let (mut writer, reader) = tokio::io::duplex(STREAM_BUFFER_SIZE);
 
-    let mut bucket = Bucket::new(bucket, region, credentials)?;
+        let download = tokio::spawn(async move {
+            let code = bucket.get_object_stream(&path, &mut writer).await?;
+            debug!("file code: {:?}", code);
 
-    bucket.set_request_timeout(Some(Duration::new(290, 0)));
+            let elapsed = now.elapsed();
+            info!("read_file_stream {}. Took {:.2?}", path, elapsed);
+            Ok(code)
+        });
 
-    let elapsed_b = now.elapsed();
-    info!("bucket creation. Took {:.2?}", elapsed_b);
-    
-    let path = file_struct.path();
-    debug!("path: {:?}", path);
+        Ok(DownloadStream {
+            reader,
+            download,
+            finished: false,
+        })
+    }
+}
+
+impl SourceReader for S3Reader {
+    fn list<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<Vec<String>, SourceError>> {
+        async move {
+            let key = url.path().trim_start_matches('/');
+            let expansion = Expansion::from(key)?;
+            let prefix = match expansion {
+                // the file, or the files under it if it doesn't exist
+                Expansion::File => key,
+                Expansion::Prefix(prefix) => prefix,
+                Expansion::Glob(prefix, _) => prefix,
+            };
+
+            let now = Instant::now();
+            let bucket = self.bucket(url)?;
+            // ListObjectsV2, follows the continuation tokens until all the pages are read
+            let pages = bucket.list(prefix.to_owned(), None).await?;
+            let bucket_name = url.host_str().unwrap_or_default();
+            let keys = pages.iter().flat_map(|p| p.contents.iter()).map(|o| o.key.as_str());
+            let keys = match expansion {
+                Expansion::File => file_or_directory(key, keys),
+                _ => keys.filter(|k| expansion.matches(k)).collect(),
+            };
+            let ret: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("s3://{}/{}", bucket_name, k))
+                .collect();
+
+            let elapsed = now.elapsed();
+            info!("list {}: {} pages, {} files. Took {:.2?}", url, pages.len(), ret.len(), elapsed);
+            Ok(ret)
+        }
+        .boxed()
+    }
 
-    let data = bucket.get_object(path).await?;
+    fn open<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<SourceObject, SourceError>> {
+        async move {
+            let now = Instant::now();
+            let bucket = self.bucket(url)?;
+            let (head, _) = bucket.head_object(url.path()).await?;
+            debug!("head object: {:?}", head);
 
-    debug!("file code: {:?}", data.status_code());
+            let elapsed_b = now.elapsed();
+            info!("bucket creation. Took {:.2?}", elapsed_b);
 
-    let file: File = serde_json::from_slice(&data.bytes())?;
+            let stream = self.read_file_stream(bucket, url).await?;
+            Ok(SourceObject {
+                stream: Box::new(stream),
+                content_encoding: head.content_encoding,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Reads the object while it is downloaded. Download errors are returned when the end of the
+/// stream is reached, otherwise they would look like a truncated file.
+struct DownloadStream {
+    reader: DuplexStream,
+    download: JoinHandle<Result<u16, S3Error>>,
+    finished: bool,
+}
 
-    let elapsed = now.elapsed();
-    info!("read_file. Took {:.2?}", elapsed);
+impl AsyncRead for DownloadStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.reader).poll_read(cx, buf))?;
+        if buf.filled().len() > filled || self.finished {
+            return Poll::Ready(Ok(()));
+        }
 
-    Ok(file)
+        let result = ready!(Pin::new(&mut self.download).poll(cx));
+        self.finished = true;
+        match result {
+            Ok(Ok(_)) => Poll::Ready(Ok(())),
+            Ok(Err(e)) => Poll::Ready(Err(io::Error::other(e))),
+            Err(e) => Poll::Ready(Err(io::Error::other(e))),
+        }
+    }
 }
@@ -0,0 +1,162 @@
+// Type-ahead search over the names and URLs of the nodes
+use crate::data::model::Node;
+use crate::db::scylladb::ScyllaDbService;
+use std::collections::BTreeMap;
+use tracing::{debug, info};
+
+/// Characters of a word or URL segment that are indexed, longer queries are filtered in memory
+pub const MAX_PREFIX_CHARS: usize = 20;
+/// Rows read for a query that is filtered in memory
+const MAX_SCANNED_ROWS: i32 = 1000;
+
+/// Lowercase words of a name, split on spaces and punctuation.
+pub fn name_tokens(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Keys of a node in the prefix index and whether each key is a whole word or URL.
+/// The keys are the prefixes of the words of the name, like `chi`, and the parent path with
+/// the prefixes of the last segment of the URL, like `root/children1/chi` or `root/children1/`.
+pub fn prefix_keys(name: &str, url: &str) -> BTreeMap<String, bool> {
+    let mut keys = BTreeMap::new();
+    for token in name_tokens(name) {
+        for (key, exact) in prefixes(&token).skip(1) {
+            *keys.entry(key).or_default() |= exact;
+        }
+    }
+
+    let url = url.to_lowercase();
+    if let Some((parent, last)) = url.rsplit_once('/') {
+        for (key, exact) in prefixes(last) {
+            *keys.entry(format!("{}/{}", parent, key)).or_default() |= exact;
+        }
+    }
+    keys
+}
+
+/// Prefixes of the token up to `MAX_PREFIX_CHARS`, from the empty one.
+fn prefixes(token: &str) -> impl Iterator<Item = (String, bool)> {
+    let chars: Vec<char> = token.chars().take(MAX_PREFIX_CHARS).collect();
+    let whole = token.chars().count();
+    (0..=chars.len()).map(move |i| (chars[..i].iter().collect(), i == whole))
+}
+
+/// Finds the nodes of the ingestion whose URL starts with the query, if it has a `/`, or whose
+/// name has words starting with the words of the query. Exact matches of the name or URL are
+/// first, then the nodes are sorted by depth. Returns up to `limit` nodes.
+pub async fn search(
+    db: &ScyllaDbService,
+    ingestion_id: &str,
+    q: &str,
+    limit: usize,
+) -> Result<Vec<Node>, Box<dyn std::error::Error + Sync + Send>> {
+    let q = q.trim().to_lowercase();
+    let tokens = name_tokens(&q);
+
+    let (key, filtered) = match q.rsplit_once('/') {
+        Some((parent, last)) => {
+            let last: String = last.chars().take(MAX_PREFIX_CHARS).collect();
+            let key = format!("{}/{}", parent, last);
+            let filtered = key.len() < q.len();
+            (key, filtered)
+        }
+        None => {
+            let token = tokens.first().ok_or("Empty search query")?;
+            let key: String = token.chars().take(MAX_PREFIX_CHARS).collect();
+            let filtered = key.len() < token.len() || tokens.len() > 1;
+            (key, filtered)
+        }
+    };
+    debug!("search: key {} of query {}, filtered? {}", key, q, filtered);
+
+    let rows = if filtered { MAX_SCANNED_ROWS } else { limit as i32 };
+    let mut nodes: Vec<Node> = db
+        .search_prefix(ingestion_id, &key, rows)
+        .await?
+        .into_iter()
+        .filter_map(|r| Node::from(vec![r]))
+        .filter(|n| {
+            if q.contains('/') {
+                n.url.to_lowercase().starts_with(&q)
+            } else {
+                let words = name_tokens(&n.name);
+                tokens.iter().all(|t| words.iter().any(|w| w.starts_with(t)))
+            }
+        })
+        .collect();
+
+    // the rows are sorted by whole word match and depth, the sort is stable
+    nodes.sort_by_key(|n| {
+        let exact = n.name.to_lowercase() == q || n.url.to_lowercase() == q;
+        (!exact, n.url.matches('/').count())
+    });
+    nodes.truncate(limit);
+
+    info!("search: {} nodes found for {} in ingestion {}", nodes.len(), q, ingestion_id);
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_tokens_split_on_punctuation() {
+        assert_eq!(name_tokens("Payment-API v2_beta"), vec!["payment", "api", "v2", "beta"]);
+        assert!(name_tokens(" -_ ").is_empty());
+    }
+
+    #[test]
+    fn prefix_keys_of_the_name_and_the_url() {
+        let keys = prefix_keys("Big Box", "root/Dir/Big Box");
+        let expected = BTreeMap::from(
+            [
+                ("b", false),
+                ("bi", false),
+                ("big", true),
+                ("bo", false),
+                ("box", true),
+                ("root/dir/", false),
+                ("root/dir/b", false),
+                ("root/dir/bi", false),
+                ("root/dir/big", false),
+                ("root/dir/big ", false),
+                ("root/dir/big b", false),
+                ("root/dir/big bo", false),
+                ("root/dir/big box", true),
+            ]
+            .map(|(k, exact)| (k.to_owned(), exact)),
+        );
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn prefix_keys_of_a_root_only_have_the_name() {
+        let keys = prefix_keys("ab", "ab");
+        assert_eq!(keys, BTreeMap::from([("a".to_owned(), false), ("ab".to_owned(), true)]));
+    }
+
+    #[test]
+    fn prefix_keys_are_exact_if_any_word_is_whole() {
+        // `ab` is a prefix of `abc` and the whole second word
+        let keys = prefix_keys("abc ab", "abc ab");
+        assert_eq!(keys.get("ab"), Some(&true));
+        assert_eq!(keys.get("abc"), Some(&true));
+        assert_eq!(keys.get("a"), Some(&false));
+    }
+
+    #[test]
+    fn prefix_keys_are_capped() {
+        let name = "a".repeat(MAX_PREFIX_CHARS + 5);
+        let keys = prefix_keys(&name, &format!("root/{}", name));
+
+        let longest = "a".repeat(MAX_PREFIX_CHARS);
+        assert_eq!(keys.get(&longest), Some(&false));
+        assert!(!keys.contains_key(&name));
+        assert_eq!(keys.get(&format!("root/{}", longest)), Some(&false));
+        assert_eq!(keys.len(), 2 * MAX_PREFIX_CHARS + 1);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::source::SourceStream;
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, ZstdDecoder};
+use strum_macros::Display;
+use tokio::io::BufReader;
+
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detects the compression from the Content-Encoding or, if it is not set, from the file extension.
+    pub fn detect(path: &str, content_encoding: Option<&str>) -> Self {
+        let from_encoding = match content_encoding.map(|e| e.trim().to_lowercase()).as_deref() {
+            Some("gzip") | Some("x-gzip") => Some(Compression::Gzip),
+            Some("zstd") => Some(Compression::Zstd),
+            Some("bzip2") | Some("x-bzip2") => Some(Compression::Bzip2),
+            _ => None,
+        };
+
+        from_encoding.unwrap_or_else(|| {
+            let path = path.to_lowercase();
+            if path.ends_with(".gz") || path.ends_with(".gzip") {
+                Compression::Gzip
+            } else if path.ends_with(".zst") || path.ends_with(".zstd") {
+                Compression::Zstd
+            } else if path.ends_with(".bz2") {
+                Compression::Bzip2
+            } else {
+                Compression::None
+            }
+        })
+    }
+
+    /// Decompresses the stream while it is read.
+    pub fn decoder(self, stream: SourceStream) -> SourceStream {
+        let stream = BufReader::new(stream);
+        match self {
+            Compression::None => Box::new(stream),
+            Compression::Gzip => {
+                let mut decoder = GzipDecoder::new(stream);
+                // concatenated gzip files are common in data lake exports
+                decoder.multiple_members(true);
+                Box::new(decoder)
+            }
+            Compression::Zstd => {
+                let mut decoder = ZstdDecoder::new(stream);
+                decoder.multiple_members(true);
+                Box::new(decoder)
+            }
+            Compression::Bzip2 => {
+                let mut decoder = BzDecoder::new(stream);
+                decoder.multiple_members(true);
+                Box::new(decoder)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn detect_from_the_extension() {
+        assert_eq!(Compression::detect("/exports/a.json.gz", None), Compression::Gzip);
+        assert_eq!(Compression::detect("/exports/a.JSON.GZIP", None), Compression::Gzip);
+        assert_eq!(Compression::detect("/exports/a.ndjson.zst", None), Compression::Zstd);
+        assert_eq!(Compression::detect("/exports/a.json.zstd", None), Compression::Zstd);
+        assert_eq!(Compression::detect("/exports/a.json.bz2", None), Compression::Bzip2);
+        assert_eq!(Compression::detect("/exports/a.json", None), Compression::None);
+    }
+
+    #[test]
+    fn content_encoding_wins_over_the_extension() {
+        assert_eq!(Compression::detect("/a.json", Some("gzip")), Compression::Gzip);
+        assert_eq!(Compression::detect("/a.json", Some(" X-GZIP ")), Compression::Gzip);
+        assert_eq!(Compression::detect("/a.json.gz", Some("zstd")), Compression::Zstd);
+        assert_eq!(Compression::detect("/a.json", Some("x-bzip2")), Compression::Bzip2);
+        // unknown encodings fall back to the extension
+        assert_eq!(Compression::detect("/a.json.bz2", Some("identity")), Compression::Bzip2);
+    }
+
+    #[tokio::test]
+    async fn decoder_reads_concatenated_gzip_members() {
+        use async_compression::tokio::write::GzipEncoder;
+        use tokio::io::AsyncWriteExt;
+
+        let mut data = vec![];
+        for part in ["{\"a\": 1}\n", "{\"b\": 2}\n"] {
+            let mut encoder = GzipEncoder::new(vec![]);
+            encoder.write_all(part.as_bytes()).await.unwrap();
+            encoder.shutdown().await.unwrap();
+            data.extend(encoder.into_inner());
+        }
+
+        let mut text = String::new();
+        Compression::Gzip
+            .decoder(Box::new(std::io::Cursor::new(data)))
+            .read_to_string(&mut text)
+            .await
+            .unwrap();
+        assert_eq!(text, "{\"a\": 1}\n{\"b\": 2}\n");
+    }
+}
@@ -0,0 +1,149 @@
+use crate::source::{Expansion, SourceError, SourceObject, SourceReader};
+use futures::future::{BoxFuture, FutureExt};
+use std::path::PathBuf;
+use tracing::info;
+use url::Url;
+
+/// Reads `file://` URLs. Paths are relative to `root`, files outside of it can't be read.
+pub struct FileReader {
+    root: PathBuf,
+}
+
+impl FileReader {
+    pub fn new(root: &str) -> Result<Self, SourceError> {
+        let root = std::fs::canonicalize(root)?;
+        info!("FileReader: Local source dir {:?}", root);
+        Ok(Self { root })
+    }
+
+    fn resolve(&self, url: &Url) -> Result<PathBuf, SourceError> {
+        let path = url.path().trim_start_matches('/');
+        let path = std::fs::canonicalize(self.root.join(path))?;
+        if !path.starts_with(&self.root) {
+            return Err(format!("{} is outside of the local source dir", url).into());
+        }
+        Ok(path)
+    }
+}
+
+impl SourceReader for FileReader {
+    fn list<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<Vec<String>, SourceError>> {
+        async move {
+            let path = url.path().trim_start_matches('/');
+            let dir = format!("{}/", path);
+            let expansion = match Expansion::from(path)? {
+                // a directory is listed like a prefix
+                Expansion::File if self.root.join(path).is_dir() => Expansion::Prefix(&dir),
+                expansion => expansion,
+            };
+            let pattern = match expansion {
+                Expansion::File => return Ok(vec![url.to_string()]),
+                Expansion::Prefix(prefix) => format!("{}**/*", prefix),
+                Expansion::Glob(_, _) => path.to_owned(),
+            };
+
+            let root = self.root.to_string_lossy();
+            let mut ret = vec![];
+            for entry in glob::glob(&format!("{}/{}", root, pattern))? {
+                let entry = entry?;
+                if !entry.is_file() {
+                    continue;
+                }
+                if let Ok(relative) = entry.strip_prefix(&self.root) {
+                    let relative = relative.to_string_lossy();
+                    if expansion.matches(&relative) {
+                        ret.push(format!("file:///{}", relative));
+                    }
+                }
+            }
+            Ok(ret)
+        }
+        .boxed()
+    }
+
+    fn open<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<SourceObject, SourceError>> {
+        async move {
+            let path = self.resolve(url)?;
+            info!("Reading local file: {:?}", path);
+            let file = tokio::fs::File::open(path).await?;
+            Ok(SourceObject {
+                stream: Box::new(file),
+                content_encoding: None,
+            })
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A source dir `root` with `data/nodes.json`, and `outside.json` next to it.
+    fn reader() -> (tempfile::TempDir, FileReader) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("root/data")).unwrap();
+        fs::write(dir.path().join("root/data/nodes.json"), "{}").unwrap();
+        fs::write(dir.path().join("outside.json"), "{}").unwrap();
+        let reader = FileReader::new(dir.path().join("root").to_str().unwrap()).unwrap();
+        (dir, reader)
+    }
+
+    fn resolve(reader: &FileReader, url: &str) -> Result<PathBuf, SourceError> {
+        reader.resolve(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn resolve_a_path_inside_the_root() {
+        let (_dir, reader) = reader();
+        let path = resolve(&reader, "file:///data/nodes.json").unwrap();
+
+        assert_eq!(path, reader.root.join("data/nodes.json"));
+        assert_eq!(resolve(&reader, "file:///data/../data/nodes.json").unwrap(), path);
+    }
+
+    #[test]
+    fn resolve_keeps_dot_dot_inside_the_root() {
+        let (_dir, reader) = reader();
+
+        // the URL is normalized, `..` can't go above the root
+        assert!(resolve(&reader, "file:///../outside.json").is_err());
+        assert!(resolve(&reader, "file:///data/../../outside.json").is_err());
+        assert!(resolve(&reader, "file:///%2e%2e/outside.json").is_err());
+    }
+
+    #[test]
+    fn resolve_an_absolute_path_from_the_root() {
+        let (dir, reader) = reader();
+        let outside = fs::canonicalize(dir.path().join("outside.json")).unwrap();
+
+        // the absolute path is read as a path of the root, where it doesn't exist
+        assert!(resolve(&reader, &format!("file://{}", outside.display())).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_a_link_out_of_the_root() {
+        let (dir, reader) = reader();
+        std::os::unix::fs::symlink(dir.path().join("outside.json"), reader.root.join("link.json"))
+            .unwrap();
+
+        let error = resolve(&reader, "file:///link.json").unwrap_err();
+        assert!(error.to_string().contains("outside of the local source dir"));
+    }
+
+    #[tokio::test]
+    async fn list_a_directory_with_or_without_the_trailing_slash() {
+        let (_dir, reader) = reader();
+        let reader = &reader;
+        let list = |url: &str| {
+            let url = Url::parse(url).unwrap();
+            async move { reader.list(&url).await.unwrap() }
+        };
+
+        assert_eq!(list("file:///data/").await, vec!["file:///data/nodes.json"]);
+        assert_eq!(list("file:///data").await, vec!["file:///data/nodes.json"]);
+        assert_eq!(list("file:///data/nodes.json").await, vec!["file:///data/nodes.json"]);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::source::{SourceError, SourceObject, SourceReader};
+use futures::future::{BoxFuture, FutureExt};
+use futures::TryStreamExt;
+use std::io;
+use tokio_util::io::StreamReader;
+use tracing::{debug, info};
+use url::Url;
+
+/// Reads `http://` and `https://` URLs from plain object servers.
+pub struct HttpReader {
+    client: reqwest::Client,
+}
+
+impl HttpReader {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl SourceReader for HttpReader {
+    fn open<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<SourceObject, SourceError>> {
+        async move {
+            info!("Reading file: {}", url);
+            let response = self
+                .client
+                .get(url.clone())
+                .send()
+                .await?
+                .error_for_status()?;
+            debug!("file code: {:?}", response.status());
+            let content_encoding = response
+                .headers()
+                .get(reqwest::header::CONTENT_ENCODING)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned());
+
+            let stream = response
+                .bytes_stream()
+                .map_err(io::Error::other);
+            Ok(SourceObject {
+                stream: Box::new(StreamReader::new(stream)),
+                content_encoding,
+            })
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,205 @@
+pub mod compression;
+pub mod file;
+pub mod http;
+
+use crate::s3::s3::S3Reader;
+use futures::future::{BoxFuture, FutureExt};
+use std::collections::HashSet;
+use std::error::Error;
+use tokio::io::AsyncRead;
+use tracing::info;
+use url::Url;
+
+use self::compression::Compression;
+use self::file::FileReader;
+use self::http::HttpReader;
+
+pub type SourceError = Box<dyn Error + Sync + Send>;
+
+pub type SourceStream = Box<dyn AsyncRead + Send + Unpin>;
+
+pub struct SourceObject {
+    pub stream: SourceStream,
+    /// Content-Encoding reported by the storage
+    pub content_encoding: Option<String>,
+}
+
+const GLOB_CHARS: &[char] = &['*', '?', '['];
+
+/// Reads source files from a storage backend.
+pub trait SourceReader: Send + Sync {
+    /// Opens the file as a byte stream, errors while reading are returned by the stream.
+    fn open<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<SourceObject, SourceError>>;
+
+    /// Expands a glob pattern or a prefix into the matching files.
+    /// Backends that can't list files return the URL as is.
+    fn list<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Result<Vec<String>, SourceError>> {
+        async move { Ok(vec![url.to_string()]) }.boxed()
+    }
+}
+
+/// How a path of a file URL has to be expanded.
+pub enum Expansion<'a> {
+    /// A single file
+    File,
+    /// All the files under the prefix
+    Prefix(&'a str),
+    /// The files under the prefix matching the pattern
+    Glob(&'a str, glob::Pattern),
+}
+
+impl<'a> Expansion<'a> {
+    /// `path` is relative to the bucket or directory, without the leading `/`.
+    pub fn from(path: &'a str) -> Result<Self, SourceError> {
+        if let Some(i) = path.find(GLOB_CHARS) {
+            let pattern = glob::Pattern::new(path)?;
+            return Ok(Expansion::Glob(&path[..i], pattern));
+        }
+        if path.is_empty() || path.ends_with('/') {
+            return Ok(Expansion::Prefix(path));
+        }
+        Ok(Expansion::File)
+    }
+
+    /// Wildcards don't match `/`, `*` only matches within a "directory" like in a shell.
+    pub fn matches(&self, path: &str) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        match self {
+            Expansion::File => false,
+            Expansion::Prefix(prefix) => path.starts_with(prefix) && !path.ends_with('/'),
+            Expansion::Glob(_, pattern) => pattern.matches_with(path, options),
+        }
+    }
+}
+
+/// Files of a path without wildcards or trailing `/`, from the `keys` listed with the path as
+/// prefix: the file itself if it exists, otherwise the files under it as if the path ended in `/`.
+pub fn file_or_directory<'k>(path: &str, keys: impl IntoIterator<Item = &'k str>) -> Vec<&'k str> {
+    let keys: Vec<&str> = keys.into_iter().collect();
+    if let Some(file) = keys.iter().find(|k| **k == path) {
+        return vec![*file];
+    }
+    let dir = format!("{}/", path);
+    keys.into_iter()
+        .filter(|k| k.starts_with(&dir) && !k.ends_with('/'))
+        .collect()
+}
+
+/// Source readers, selected by the scheme of the file URL.
+pub struct SourceReaders {
+    s3: S3Reader,
+    file: Option<FileReader>,
+    http: HttpReader,
+}
+
+impl SourceReaders {
+    pub fn new(s3: S3Reader, file: Option<FileReader>, http: HttpReader) -> Self {
+        Self { s3, file, http }
+    }
+
+    pub fn for_url(&self, url: &Url) -> Result<&dyn SourceReader, SourceError> {
+        match url.scheme() {
+            "s3" => Ok(&self.s3),
+            "http" | "https" => Ok(&self.http),
+            "file" => match &self.file {
+                Some(reader) => Ok(reader),
+                None => Err("Local files are disabled, set LOCAL_SOURCE_DIR to enable them".into()),
+            },
+            scheme => Err(format!("Unsupported source scheme: {}", scheme).into()),
+        }
+    }
+
+    /// Opens the file, compressed files are decompressed while they are read.
+    pub async fn open(&self, file: &str) -> Result<SourceStream, SourceError> {
+        let url = Url::parse(file)?;
+        let object = self.for_url(&url)?.open(&url).await?;
+        let compression = Compression::detect(url.path(), object.content_encoding.as_deref());
+        info!("File {} compression: {}", file, compression);
+        Ok(compression.decoder(object.stream))
+    }
+
+    /// Expands the glob patterns and prefixes, files are returned once in the order they were found.
+    pub async fn expand(&self, files: &[String]) -> Result<Vec<String>, SourceError> {
+        let mut ret: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+        for file in files {
+            let url = Url::parse(file)?;
+            let expanded = self.for_url(&url)?.list(&url).await?;
+            if expanded.is_empty() {
+                return Err(format!("No files found for {}", file).into());
+            }
+            info!("Expanded {} into {} files", file, expanded.len());
+            for f in expanded {
+                if seen.insert(f.clone()) {
+                    ret.push(f);
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion_from_path() {
+        assert!(matches!(Expansion::from("exports/part-1.json").unwrap(), Expansion::File));
+        assert!(matches!(Expansion::from("exports/").unwrap(), Expansion::Prefix("exports/")));
+        assert!(matches!(Expansion::from("").unwrap(), Expansion::Prefix("")));
+        assert!(matches!(
+            Expansion::from("exports/2026-*/part-?.json").unwrap(),
+            Expansion::Glob("exports/2026-", _)
+        ));
+        assert!(Expansion::from("[abc").is_err());
+    }
+
+    #[test]
+    fn prefix_matches_the_files_under_it() {
+        let expansion = Expansion::from("exports/").unwrap();
+        assert!(expansion.matches("exports/part-1.json"));
+        assert!(expansion.matches("exports/2026/part-1.json"));
+        assert!(!expansion.matches("exports/2026/"));
+        assert!(!expansion.matches("other/part-1.json"));
+    }
+
+    #[test]
+    fn glob_wildcards_do_not_match_the_separator() {
+        let expansion = Expansion::from("exports/2026-*/part-?.json").unwrap();
+        assert!(expansion.matches("exports/2026-10-17/part-1.json"));
+        assert!(!expansion.matches("exports/2026-10/17/part-1.json"));
+        assert!(!expansion.matches("exports/2026-10-17/part-12.json"));
+
+        let expansion = Expansion::from("exports/part-[12].json").unwrap();
+        assert!(expansion.matches("exports/part-2.json"));
+        assert!(!expansion.matches("exports/part-3.json"));
+    }
+
+    #[test]
+    fn file_or_directory_prefers_the_file() {
+        let keys = ["exports/part-1.json", "exports/part-1.json.bak", "exports/part-1.json/x.json"];
+        assert_eq!(file_or_directory("exports/part-1.json", keys), vec!["exports/part-1.json"]);
+    }
+
+    #[test]
+    fn file_or_directory_lists_a_bare_prefix() {
+        let keys = ["exports/a.json", "exports/b/c.json", "exports/b/", "exports-old/a.json"];
+
+        // without the trailing `/` it is read like `exports/`
+        assert_eq!(file_or_directory("exports", keys), vec!["exports/a.json", "exports/b/c.json"]);
+        let prefix = Expansion::from("exports/").unwrap();
+        let listed: Vec<&str> = keys.into_iter().filter(|k| prefix.matches(k)).collect();
+        assert_eq!(listed, file_or_directory("exports", keys));
+
+        assert!(file_or_directory("missing", keys).is_empty());
+    }
+
+    #[test]
+    fn file_matches_nothing() {
+        assert!(!Expansion::from("exports/part-1.json").unwrap().matches("exports/part-1.json"));
+    }
+}
@@ -0,0 +1,184 @@
+// Subtree statistics of the nodes of an ingestion
+use crate::data::model::get_id_from_url;
+use crate::db::model::DbNodeStats;
+use crate::db::scylladb::ScyllaDbService;
+use std::collections::HashMap;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+struct Entry {
+    parent: Option<Uuid>,
+    node_type: String,
+    depth: i32,
+}
+
+/// Computes the children, descendants, depth and descendants by type of every node of the
+/// ingestion and saves them with the number of nodes of each type of the ingestion. The hierarchy
+/// is read from the URLs of the nodes in the manifest, so it works the same for every source
+/// format and after a delta. Returns the number of nodes, None if it was cancelled before
+/// saving them.
+pub async fn compute_stats(
+    db: &ScyllaDbService,
+    ingestion_id: &str,
+    token: &CancellationToken,
+) -> Result<Option<usize>, Box<dyn std::error::Error + Sync + Send>> {
+    let nodes = match read_hierarchy(db, ingestion_id, token).await? {
+        Some(nodes) => nodes,
+        None => return Ok(None),
+    };
+    debug!("compute_stats: {} nodes in ingestion {}", nodes.len(), ingestion_id);
+
+    let mut type_counts: HashMap<String, i64> = HashMap::new();
+    for (_, node_type) in &nodes {
+        *type_counts.entry(node_type.clone()).or_default() += 1;
+    }
+    db.save_type_counts(ingestion_id, type_counts).await?;
+
+    let stats = subtree_stats(ingestion_id, nodes);
+    let saved = db.save_node_stats(stats.into_values().collect()).await?;
+    info!("compute_stats: statistics of {} nodes of ingestion {} saved", saved, ingestion_id);
+    Ok(Some(saved))
+}
+
+/// Statistics of every node from the URL and type of all the nodes of the ingestion.
+/// A node whose parent is not in the ingestion is not counted by any node.
+fn subtree_stats(ingestion_id: &str, nodes: Vec<(String, String)>) -> HashMap<Uuid, DbNodeStats> {
+    let entries: HashMap<Uuid, Entry> = nodes
+        .into_iter()
+        .map(|(url, node_type)| {
+            let parent = url
+                .rsplit_once('/')
+                .map(|(parent, _)| get_id_from_url(ingestion_id.to_owned(), parent.to_owned()));
+            let entry = Entry {
+                parent,
+                node_type,
+                depth: url.matches('/').count() as i32,
+            };
+            (get_id_from_url(ingestion_id.to_owned(), url), entry)
+        })
+        .collect();
+
+    let mut stats: HashMap<Uuid, DbNodeStats> = entries
+        .iter()
+        .map(|(id, e)| {
+            let s = DbNodeStats {
+                uuid: *id,
+                ingestion_id: ingestion_id.to_owned(),
+                depth: e.depth,
+                ..Default::default()
+            };
+            (*id, s)
+        })
+        .collect();
+
+    // the deepest nodes first so every subtree is complete when it is added to its parent
+    let mut ids: Vec<&Uuid> = entries.keys().collect();
+    ids.sort_by_key(|id| std::cmp::Reverse(entries[id].depth));
+    for id in ids {
+        let entry = &entries[id];
+        let parent = match entry.parent.filter(|p| stats.contains_key(p)) {
+            Some(parent) => parent,
+            None => continue,
+        };
+        let (descendants, types) = {
+            let s = &stats[id];
+            (s.descendants, s.types.clone().unwrap_or_default())
+        };
+
+        let p = stats.get_mut(&parent).unwrap();
+        p.children += 1;
+        p.descendants += 1 + descendants;
+        let counts = p.types.get_or_insert_with(HashMap::new);
+        *counts.entry(entry.node_type.clone()).or_default() += 1;
+        for (node_type, count) in types {
+            *counts.entry(node_type).or_default() += count;
+        }
+    }
+    stats
+}
+
+/// URL and type of every node of the ingestion, read page by page from its manifest.
+/// Ids of the manifest without a node, like the nodes removed by a delta, are skipped.
+/// Returns None if it is cancelled.
+async fn read_hierarchy(
+    db: &ScyllaDbService,
+    ingestion_id: &str,
+    token: &CancellationToken,
+) -> Result<Option<Vec<(String, String)>>, Box<dyn std::error::Error + Sync + Send>> {
+    let mut nodes = vec![];
+    let mut paging_state = None;
+    loop {
+        if token.is_cancelled() {
+            return Ok(None);
+        }
+        let (ids, next) = db.get_ingestion_nodes(ingestion_id, paging_state).await?;
+        let keys = ids.iter().map(|id| id.to_string()).collect();
+        for (_, result) in db.get_nodes(keys, false, false).await {
+            if let Some(node) = result?.into_iter().next() {
+                nodes.push((node.url, node.node_type));
+            }
+        }
+
+        paging_state = next;
+        if paging_state.is_none() {
+            break;
+        }
+    }
+    Ok(Some(nodes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(url: &str) -> Uuid {
+        get_id_from_url("test".to_owned(), url.to_owned())
+    }
+
+    fn stats_of(nodes: &[(&str, &str)]) -> HashMap<Uuid, DbNodeStats> {
+        let nodes = nodes
+            .iter()
+            .map(|(url, node_type)| (url.to_string(), node_type.to_string()))
+            .collect();
+        subtree_stats("test", nodes)
+    }
+
+    #[test]
+    fn subtree_stats_of_nested_levels() {
+        let stats = stats_of(&[
+            ("a", "root"),
+            ("a/b", "folder"),
+            ("a/b/c", "file"),
+            ("a/b/d", "file"),
+            ("a/e", "file"),
+        ]);
+
+        let a = &stats[&id("a")];
+        assert_eq!((a.children, a.descendants, a.depth), (2, 4, 0));
+        let types = a.types.as_ref().unwrap();
+        assert_eq!(types["folder"], 1);
+        assert_eq!(types["file"], 3);
+
+        let b = &stats[&id("a/b")];
+        assert_eq!((b.children, b.descendants, b.depth), (2, 2, 1));
+        assert_eq!(b.types.as_ref().unwrap()["file"], 2);
+
+        let c = &stats[&id("a/b/c")];
+        assert_eq!((c.children, c.descendants, c.depth), (0, 0, 2));
+        assert!(c.types.is_none());
+    }
+
+    #[test]
+    fn subtree_stats_skip_a_missing_parent() {
+        // the parent x was removed by a delta
+        let stats = stats_of(&[("a", "root"), ("a/x/y", "file"), ("a/x/y/z", "file")]);
+
+        assert_eq!(stats.len(), 3);
+        assert!(!stats.contains_key(&id("a/x")));
+        let a = &stats[&id("a")];
+        assert_eq!((a.children, a.descendants), (0, 0));
+        let y = &stats[&id("a/x/y")];
+        assert_eq!((y.children, y.descendants, y.depth), (1, 1, 2));
+    }
+}
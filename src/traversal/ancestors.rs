@@ -0,0 +1,100 @@
+// Ancestors of a node in the hierarchy
+use crate::data::model::{get_id_from_url, Node};
+use crate::db::scylladb::ScyllaDbService;
+use crate::traversal::error::TraversalError;
+use crate::{DIR, REL};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Gets the ancestors of the node, from the root to its parent. With `from_url` the ancestors
+/// are the prefixes of the URL of the node, read in one batch, otherwise the ISPARENT relations
+/// are followed up to the root, one level at a time. Ancestors that don't exist are skipped.
+pub async fn ancestors(
+    db: &ScyllaDbService,
+    id: Uuid,
+    from_url: bool,
+    tags: bool,
+    max_depth: usize,
+) -> Result<Vec<Node>, TraversalError> {
+    let ids = if from_url {
+        ancestors_from_url(db, id).await?
+    } else {
+        ancestors_from_relations(db, id, max_depth).await?
+    };
+    debug!("ancestors: {} ancestors of {}", ids.len(), id);
+
+    let mut nodes: HashMap<Uuid, Node> = HashMap::new();
+    let keys = ids.iter().map(|id| id.to_string()).collect();
+    for (key, result) in db.get_nodes(keys, tags, false).await {
+        let uuid = Uuid::parse_str(&key).unwrap_or_default();
+        let rows = result.map_err(|message| TraversalError::Read { id: uuid, message })?;
+        if let Some(node) = Node::from(rows) {
+            nodes.insert(uuid, node);
+        }
+    }
+
+    info!("ancestors: {} ancestors of {} read", nodes.len(), id);
+    Ok(ids.iter().filter_map(|id| nodes.remove(id)).collect())
+}
+
+/// Ids of the prefixes of the URL of the node, the root first.
+async fn ancestors_from_url(db: &ScyllaDbService, id: Uuid) -> Result<Vec<Uuid>, TraversalError> {
+    let rows = db
+        .get_node(&id.to_string(), false, false)
+        .await
+        .map_err(|e| read_error(id, e))?;
+    let node = Node::from(rows).ok_or(TraversalError::NotFound(id))?;
+
+    let names: Vec<&str> = node.url.split('/').collect();
+    let ids = (1..names.len())
+        .map(|i| get_id_from_url(node.ingestion_id.clone(), names[..i].join("/")))
+        .collect();
+    Ok(ids)
+}
+
+/// Ids of the parents of the node following the ISPARENT relations, the root first.
+async fn ancestors_from_relations(
+    db: &ScyllaDbService,
+    id: Uuid,
+    max_depth: usize,
+) -> Result<Vec<Uuid>, TraversalError> {
+    let direction = DIR::IN.to_string();
+    let relation = Some(REL::ISPARENT.to_string());
+
+    let mut ids = vec![];
+    let mut visited = HashSet::from([id]);
+    let mut current = id;
+    while ids.len() < max_depth {
+        let rows = db
+            .get_node_traversal(&current.to_string(), &direction, &relation)
+            .await
+            .map_err(|e| read_error(current, e))?;
+        if current == id && rows.first().map(|r| r.direction.as_deref()) != Some(Some("")) {
+            return Err(TraversalError::NotFound(id));
+        }
+
+        let parent = rows
+            .iter()
+            .filter(|r| r.direction.as_deref() == Some(direction.as_str()))
+            .find_map(|r| r.relates_to.as_deref().and_then(|p| Uuid::parse_str(p).ok()));
+        match parent {
+            // a cycle in the hierarchy, stop at the first repeated node
+            Some(parent) if visited.insert(parent) => {
+                ids.push(parent);
+                current = parent;
+            }
+            _ => break,
+        }
+    }
+
+    ids.reverse();
+    Ok(ids)
+}
+
+fn read_error(id: Uuid, e: Box<dyn std::error::Error + Sync + Send>) -> TraversalError {
+    TraversalError::Read {
+        id,
+        message: e.to_string(),
+    }
+}
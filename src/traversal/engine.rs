@@ -0,0 +1,489 @@
+// Breadth first traversal of the graph
+use crate::data::model::{Marker, TraversalNode};
+use crate::data::rest_api::{
+    FlatEdge, FlatNode, FlatTraversalResponse, Truncation, TraversalResponse,
+};
+use crate::db::scylladb::ScyllaDbService;
+use crate::traversal::error::TraversalError;
+use futures::stream::{self, Stream, StreamExt};
+use std::future::Future;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// Limits of the traversals, the requests can lower `max_nodes` and `timeout`
+#[derive(Debug, Clone, Copy)]
+pub struct TraversalLimits {
+    pub max_nodes: usize,
+    pub timeout: Duration,
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TraversalOptions {
+    pub direction: String,
+    pub relation_type: Option<String>,
+    pub max_depth: usize,
+    pub limits: TraversalLimits,
+    /// Fail on the first node that can't be read or on timeout instead of returning a partial tree
+    pub strict: bool,
+    /// Return the tags of the nodes
+    pub tags: bool,
+}
+
+/// Result of reading the nodes of the traversal
+#[derive(Default)]
+struct Visit {
+    fetched: HashMap<Uuid, TraversalNode>,
+    // nodes reached, in the order they were reached
+    order: Vec<Uuid>,
+    // nodes that could not be read, with the error
+    failed: HashMap<Uuid, String>,
+    // nodes without root row, like the targets of edges of nodes that were not ingested
+    missing: HashSet<Uuid>,
+    // the parent and the index of its relation that reached each node first
+    discovered: HashMap<Uuid, Option<(Uuid, usize)>>,
+}
+
+impl Visit {
+    /// True if the node was read, even if it failed or it does not exist
+    fn is_read(&self, id: &Uuid) -> bool {
+        self.fetched.contains_key(id) || self.failed.contains_key(id) || self.missing.contains(id)
+    }
+}
+
+/// Nodes read by a traversal, returned as a tree or as a flat list of nodes and edges
+pub struct Traversal {
+    root: Uuid,
+    max_depth: usize,
+    direction: String,
+    visit: Visit,
+    truncated: Option<Truncation>,
+}
+
+/// Traverses the graph level by level from `id`. Every node is expanded once, the other
+/// relations to it are returned as `cycle` or `visited` markers. Stops when `max_nodes` nodes
+/// are read or on timeout, returning the nodes read so far. Nodes that can't be read are
+/// returned with their error, unless it is `strict`.
+pub async fn traverse(
+    db: &ScyllaDbService,
+    id: Uuid,
+    options: &TraversalOptions,
+) -> Result<Traversal, TraversalError> {
+    let deadline = Instant::now() + options.limits.timeout;
+    let mut visit = Visit {
+        order: vec![id],
+        discovered: HashMap::from([(id, None)]),
+        ..Default::default()
+    };
+    let mut truncated = None;
+
+    let mut level = vec![id];
+    for depth in 0..=options.max_depth {
+        if level.is_empty() {
+            break;
+        }
+        let budget = options.limits.max_nodes.saturating_sub(visit.fetched.len());
+        if level.len() > budget {
+            level.truncate(budget);
+            truncated = Some(Truncation::MaxNodes);
+        }
+        debug!("traverse: depth {}, {} nodes", depth, level.len());
+
+        let reads = stream::iter(level)
+            .map(|id| async move { (id, read_node(db, id, options, depth).await) });
+        let (results, timed_out) = read_until(reads, options.limits.concurrency, deadline).await;
+        if timed_out {
+            // without the start node there is no partial tree to return
+            if options.strict || depth == 0 {
+                return Err(TraversalError::Timeout);
+            }
+            // the nodes of the level read before the timeout are kept
+            truncated = Some(Truncation::Timeout);
+        }
+
+        let mut next = vec![];
+        for (node_id, result) in results {
+            let node = match result {
+                Ok(Some(node)) => node,
+                Ok(None) if node_id == id => return Err(TraversalError::NotFound(id)),
+                Ok(None) => {
+                    visit.missing.insert(node_id);
+                    continue;
+                }
+                Err(e) if options.strict || node_id == id => return Err(e),
+                Err(e) => {
+                    visit.failed.insert(node_id, e.to_string());
+                    continue;
+                }
+            };
+            if depth < options.max_depth {
+                for (i, edge) in node.relation_edges.iter().enumerate() {
+                    if let Entry::Vacant(e) = visit.discovered.entry(edge.relates_to) {
+                        e.insert(Some((node_id, i)));
+                        visit.order.push(edge.relates_to);
+                        next.push(edge.relates_to);
+                    }
+                }
+            }
+            visit.fetched.insert(node_id, node);
+        }
+
+        if truncated.is_some() {
+            break;
+        }
+        level = next;
+    }
+
+    info!(
+        "traverse: {} nodes read. Errors: {}. Truncated: {:?}",
+        visit.fetched.len(),
+        visit.failed.len(),
+        truncated
+    );
+    Ok(Traversal {
+        root: id,
+        max_depth: options.max_depth,
+        direction: options.direction.clone(),
+        visit,
+        truncated,
+    })
+}
+
+/// Runs the reads, `concurrency` at a time, until they are all done or the deadline.
+/// Returns the results of the reads done, in the order of the reads so the tree does not
+/// depend on the response times, and whether it timed out.
+async fn read_until<S, F>(reads: S, concurrency: usize, deadline: Instant) -> (Vec<F::Output>, bool)
+where
+    S: Stream<Item = F>,
+    F: Future,
+{
+    let pending = reads
+        .enumerate()
+        .map(|(i, read)| async move { (i, read.await) })
+        .buffer_unordered(concurrency);
+    futures::pin_mut!(pending);
+    let mut results = vec![];
+    let mut timed_out = false;
+    loop {
+        match timeout_at(deadline, pending.next()).await {
+            Ok(Some(result)) => results.push(result),
+            Ok(None) => break,
+            Err(_) => {
+                timed_out = true;
+                break;
+            }
+        }
+    }
+    results.sort_by_key(|(i, _)| *i);
+    (results.into_iter().map(|(_, r)| r).collect(), timed_out)
+}
+
+impl Traversal {
+    pub fn tree(&self) -> TraversalResponse {
+        TraversalResponse {
+            root: build_tree(self.root, self.max_depth, &self.visit, &mut vec![]),
+            nodes: self.visit.fetched.len(),
+            errors: self.visit.failed.len(),
+            truncated: self.truncated,
+        }
+    }
+
+    /// Every node reached once, in the order they were reached, and all the relations between them.
+    pub fn flat(&self) -> FlatTraversalResponse {
+        let visit = &self.visit;
+        let mut nodes = vec![];
+        let mut edges = vec![];
+
+        for id in &visit.order {
+            if let Some(node) = visit.fetched.get(id) {
+                nodes.push(FlatNode {
+                    uuid: node.uuid,
+                    depth: node.depth,
+                    name: node.name.clone(),
+                    node_type: node.node_type.clone(),
+                    tags: node.tags.clone(),
+                    marker: None,
+                    error: None,
+                });
+                if node.depth >= self.max_depth {
+                    continue;
+                }
+                for edge in &node.relation_edges {
+                    if visit.is_read(&edge.relates_to) {
+                        edges.push(FlatEdge {
+                            source: node.uuid,
+                            target: edge.relates_to,
+                            relation: edge.relation.clone(),
+                            direction: self.direction.clone(),
+                            depth: node.depth + 1,
+                            tags: edge.tags.clone(),
+                        });
+                    }
+                }
+            } else if let Some(Some((parent, i))) = visit.discovered.get(id) {
+                // not read, named after the relation that reached it
+                let (error, marker) = match visit.failed.get(id) {
+                    Some(e) => (Some(e.clone()), None),
+                    None if visit.missing.contains(id) => (None, Some(Marker::Missing)),
+                    None => continue,
+                };
+                let parent = &visit.fetched[parent];
+                nodes.push(FlatNode {
+                    uuid: *id,
+                    depth: parent.depth + 1,
+                    name: parent.relation_edges[*i].name.clone(),
+                    node_type: String::new(),
+                    tags: None,
+                    marker,
+                    error,
+                });
+            }
+        }
+
+        FlatTraversalResponse {
+            nodes,
+            edges,
+            errors: visit.failed.len(),
+            truncated: self.truncated,
+        }
+    }
+}
+
+async fn read_node(
+    db: &ScyllaDbService,
+    id: Uuid,
+    options: &TraversalOptions,
+    depth: usize,
+) -> Result<Option<TraversalNode>, TraversalError> {
+    let rows = db
+        .get_node_traversal(&id.to_string(), &options.direction, &options.relation_type)
+        .await
+        .map_err(|e| {
+            error!("traverse: Error reading node {}: {:?}", id, e);
+            TraversalError::Read {
+                id,
+                message: e.to_string(),
+            }
+        })?;
+    let mut node = TraversalNode::from(rows, depth);
+    if let Some(node) = node.as_mut().filter(|_| !options.tags) {
+        node.tags = None;
+    }
+    Ok(node)
+}
+
+/// Builds the tree of the nodes read, a node is a child of the relation that reached it first.
+/// Nodes that could not be read are children with their error.
+fn build_tree(
+    id: Uuid,
+    max_depth: usize,
+    visit: &Visit,
+    ancestors: &mut Vec<Uuid>,
+) -> Option<TraversalNode> {
+    let mut node = visit.fetched.get(&id)?.clone();
+    let edges = std::mem::take(&mut node.relation_edges);
+    if node.depth >= max_depth {
+        return Some(node);
+    }
+
+    ancestors.push(id);
+    for (i, edge) in edges.iter().enumerate() {
+        let target = edge.relates_to;
+        let depth = node.depth + 1;
+        let reached_here = visit.discovered.get(&target) == Some(&Some((id, i)));
+        let child = if reached_here {
+            if let Some(e) = visit.failed.get(&target) {
+                Some(TraversalNode::failed(edge, depth, e.clone()))
+            } else if visit.missing.contains(&target) {
+                Some(TraversalNode::marker(edge, depth, String::new(), Marker::Missing))
+            } else {
+                build_tree(target, max_depth, visit, ancestors)
+            }
+        } else if let Some(n) = visit.fetched.get(&target) {
+            let marker = if ancestors.contains(&target) {
+                Marker::Cycle
+            } else {
+                Marker::Visited
+            };
+            Some(TraversalNode::marker(edge, depth, n.node_type.clone(), marker))
+        } else {
+            // not read, below max_depth or over max_nodes
+            None
+        };
+
+        if let Some(mut child) = child {
+            child.relation = Some(edge.relation.clone());
+            child.relation_tags = edge.tags.clone();
+            node.relations.push(child);
+        }
+    }
+    ancestors.pop();
+
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::model::DbRelation;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    fn row(n: u128, direction: &str, relation: &str, relates_to: String, name: String) -> DbRelation {
+        DbRelation {
+            uuid: id(n),
+            direction: Some(direction.to_owned()),
+            relation: Some(relation.to_owned()),
+            relates_to: Some(relates_to),
+            name,
+            node_type: "t".to_owned(),
+            tags: None,
+        }
+    }
+
+    fn node(n: u128, depth: usize, edges: &[u128]) -> TraversalNode {
+        let mut rows = vec![row(n, "", "", String::new(), format!("n{}", n))];
+        for target in edges {
+            rows.push(row(n, "OUT", "custom", id(*target).to_string(), format!("n{}", target)));
+        }
+        TraversalNode::from(rows, depth).unwrap()
+    }
+
+    /// 1 -> 2 -> 1 is a cycle, 1 -> 3 -> 2 reaches 2 again, 4 does not exist and 5 failed.
+    fn traversal() -> Traversal {
+        let mut visit = Visit {
+            order: vec![id(1), id(2), id(3), id(4), id(5)],
+            ..Default::default()
+        };
+        visit.fetched.insert(id(1), node(1, 0, &[2, 3]));
+        visit.fetched.insert(id(2), node(2, 1, &[1, 4]));
+        visit.fetched.insert(id(3), node(3, 1, &[2, 5]));
+        visit.missing.insert(id(4));
+        visit.failed.insert(id(5), "timeout".to_owned());
+        visit.discovered = HashMap::from([
+            (id(1), None),
+            (id(2), Some((id(1), 0))),
+            (id(3), Some((id(1), 1))),
+            (id(4), Some((id(2), 1))),
+            (id(5), Some((id(3), 1))),
+        ]);
+        Traversal {
+            root: id(1),
+            max_depth: 2,
+            direction: "OUT".to_owned(),
+            visit,
+            truncated: None,
+        }
+    }
+
+    #[test]
+    fn tree_marks_cycles_visited_missing_and_failed_nodes() {
+        let response = traversal().tree();
+        assert_eq!(response.nodes, 3);
+        assert_eq!(response.errors, 1);
+
+        let root = response.root.unwrap();
+        assert_eq!(root.uuid, id(1));
+        assert_eq!(root.relations.len(), 2);
+
+        let two = &root.relations[0];
+        assert_eq!(two.uuid, id(2));
+        assert_eq!(two.marker, None);
+        assert_eq!(two.relation.as_deref(), Some("custom"));
+        assert_eq!(two.relations[0].uuid, id(1));
+        assert_eq!(two.relations[0].marker, Some(Marker::Cycle));
+        assert_eq!(two.relations[1].uuid, id(4));
+        assert_eq!(two.relations[1].marker, Some(Marker::Missing));
+        assert_eq!(two.relations[1].name, "n4");
+
+        let three = &root.relations[1];
+        assert_eq!(three.relations[0].uuid, id(2));
+        assert_eq!(three.relations[0].marker, Some(Marker::Visited));
+        assert!(three.relations[0].relations.is_empty());
+        assert_eq!(three.relations[1].uuid, id(5));
+        assert_eq!(three.relations[1].error.as_deref(), Some("timeout"));
+        assert_eq!(three.relations[1].depth, 2);
+    }
+
+    #[test]
+    fn tree_stops_at_max_depth() {
+        let mut traversal = traversal();
+        traversal.max_depth = 1;
+        let root = traversal.tree().root.unwrap();
+
+        assert_eq!(root.relations.len(), 2);
+        assert!(root.relations.iter().all(|n| n.relations.is_empty()));
+    }
+
+    #[test]
+    fn flat_lists_every_node_once_and_the_edges_between_them() {
+        let response = traversal().flat();
+
+        let ids: Vec<Uuid> = response.nodes.iter().map(|n| n.uuid).collect();
+        assert_eq!(ids, vec![id(1), id(2), id(3), id(4), id(5)]);
+        assert_eq!(response.nodes[3].marker, Some(Marker::Missing));
+        assert_eq!(response.nodes[3].depth, 2);
+        assert_eq!(response.nodes[3].name, "n4");
+        assert_eq!(response.nodes[4].error.as_deref(), Some("timeout"));
+        assert_eq!(response.errors, 1);
+
+        let edges: Vec<(Uuid, Uuid, usize)> =
+            response.edges.iter().map(|e| (e.source, e.target, e.depth)).collect();
+        assert_eq!(
+            edges,
+            vec![
+                (id(1), id(2), 1),
+                (id(1), id(3), 1),
+                (id(2), id(1), 2),
+                (id(2), id(4), 2),
+                (id(3), id(2), 2),
+                (id(3), id(5), 2),
+            ]
+        );
+        assert!(response.edges.iter().all(|e| e.direction == "OUT"));
+    }
+
+    #[test]
+    fn flat_skips_the_nodes_that_were_not_read() {
+        let mut traversal = traversal();
+        // reached but not read, over max_nodes
+        traversal.visit.missing.remove(&id(4));
+        let response = traversal.flat();
+
+        assert!(response.nodes.iter().all(|n| n.uuid != id(4)));
+        assert!(response.edges.iter().all(|e| e.target != id(4)));
+    }
+
+    #[tokio::test]
+    async fn read_until_keeps_the_reads_done_before_the_deadline() {
+        let delays = [30, 5000, 10, 20];
+        let reads = stream::iter(delays.into_iter().enumerate()).map(|(i, ms)| async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            i
+        });
+        let deadline = Instant::now() + Duration::from_millis(500);
+
+        let (results, timed_out) = read_until(reads, 4, deadline).await;
+
+        assert!(timed_out);
+        // in the order of the reads, not of their responses
+        assert_eq!(results, vec![0, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn read_until_reads_all_before_the_deadline() {
+        let reads = stream::iter(0..10).map(|i| async move { i * 2 });
+        let deadline = Instant::now() + Duration::from_millis(500);
+
+        let (results, timed_out) = read_until(reads, 3, deadline).await;
+
+        assert!(!timed_out);
+        assert_eq!(results, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::data::rest_api::{AppError, Truncation};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use std::fmt;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum TraversalError {
+    /// The start node does not exist
+    NotFound(Uuid),
+    /// A node could not be read from the database
+    Read { id: Uuid, message: String },
+    /// The traversal did not finish in time, in strict mode or before reading the start node
+    Timeout,
+    /// There is no path between the nodes, or it was not found before reaching the limits
+    NoPath {
+        from: Uuid,
+        to: Uuid,
+        truncated: Option<Truncation>,
+    },
+}
+
+impl fmt::Display for TraversalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraversalError::NotFound(id) => write!(f, "Node {} not found", id),
+            TraversalError::Read { id, message } => {
+                write!(f, "Error reading node {}: {}", id, message)
+            }
+            TraversalError::Timeout => write!(f, "Traversal timed out"),
+            TraversalError::NoPath { from, to, truncated } => match truncated {
+                Some(t) => write!(f, "No path found from {} to {}, stopped by {:?}", from, to, t),
+                None => write!(f, "No path from {} to {}", from, to),
+            },
+        }
+    }
+}
+
+impl std::error::Error for TraversalError {}
+
+impl ResponseError for TraversalError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TraversalError::NotFound(_) => StatusCode::NOT_FOUND,
+            TraversalError::Read { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            TraversalError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            TraversalError::NoPath { .. } => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(AppError::new(self.to_string()))
+    }
+}
@@ -0,0 +1,4 @@
+pub mod ancestors;
+pub mod engine;
+pub mod error;
+pub mod path;
@@ -0,0 +1,374 @@
+// Shortest path between two nodes
+use crate::data::model::Node;
+use crate::data::rest_api::{FlatEdge, PathResponse, Truncation};
+use crate::db::model::DbRelation;
+use crate::db::scylladb::ScyllaDbService;
+use crate::traversal::engine::TraversalLimits;
+use crate::traversal::error::TraversalError;
+use crate::{reverse_relation, DIR};
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct PathOptions {
+    /// Direction of the relations followed from `from`, the relations are followed
+    /// in the other direction from `to`
+    pub direction: String,
+    pub relation_type: Option<String>,
+    pub max_depth: usize,
+    pub limits: TraversalLimits,
+}
+
+/// Type and tags of a relation of the path
+#[derive(Debug, Clone, Default)]
+struct Link {
+    relation: String,
+    tags: Vec<(String, String)>,
+}
+
+/// A node reached by one side of the search, with the relation that reached it
+struct Step {
+    depth: usize,
+    /// The node it was reached from and the relation
+    from: Option<(Uuid, Link)>,
+    name: String,
+}
+
+/// One side of the bidirectional search
+struct Side {
+    direction: String,
+    relation_type: Option<String>,
+    steps: HashMap<Uuid, Step>,
+    level: Vec<Uuid>,
+    depth: usize,
+}
+
+impl Side {
+    fn new(start: Uuid, direction: String, relation_type: Option<String>) -> Self {
+        let step = Step {
+            depth: 0,
+            from: None,
+            name: String::new(),
+        };
+        Self {
+            direction,
+            relation_type,
+            steps: HashMap::from([(start, step)]),
+            level: vec![start],
+            depth: 0,
+        }
+    }
+
+    /// Adds the node reached from `id` at the next depth. Returns false if it was already reached.
+    fn reach(&mut self, id: Uuid, next: Uuid, link: Link, name: String) -> bool {
+        if self.steps.contains_key(&next) {
+            return false;
+        }
+        let step = Step {
+            depth: self.depth,
+            from: Some((id, link)),
+            name,
+        };
+        self.steps.insert(next, step);
+        self.level.push(next);
+        true
+    }
+
+    /// The node of the last level of the side closest to the start of the other side, if any.
+    /// All the nodes of the level are at the same depth of this side.
+    fn meeting(&self, other: &Side) -> Option<Uuid> {
+        self.level
+            .iter()
+            .filter_map(|id| other.steps.get(id).map(|s| (s.depth, *id)))
+            .min()
+            .map(|(_, id)| id)
+    }
+
+    /// Path from the start of the side to the node, the start first.
+    fn path(&self, id: Uuid) -> Vec<(Uuid, Option<Link>)> {
+        let mut path = vec![];
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let step = &self.steps[&id];
+            current = step.from.as_ref().map(|(prev, _)| *prev);
+            path.push((id, step.from.as_ref().map(|(_, link)| link.clone())));
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// Finds a shortest path from `from` to `to` up to `max_depth` relations, expanding the
+/// smallest side of the search each time, from `from` following `direction` and from `to`
+/// following the other direction.
+pub async fn shortest_path(
+    db: &ScyllaDbService,
+    from: Uuid,
+    to: Uuid,
+    options: &PathOptions,
+) -> Result<PathResponse, TraversalError> {
+    let deadline = Instant::now() + options.limits.timeout;
+    let reverse = if options.direction == DIR::OUT.to_string() {
+        DIR::IN
+    } else {
+        DIR::OUT
+    };
+
+    let nodes = read_nodes(db, &[from, to]).await?;
+    for id in [from, to] {
+        if !nodes.contains_key(&id) {
+            return Err(TraversalError::NotFound(id));
+        }
+    }
+
+    let mut forward = Side::new(from, options.direction.clone(), options.relation_type.clone());
+    let mut backward = Side::new(
+        to,
+        reverse.to_string(),
+        options.relation_type.clone().map(reverse_relation),
+    );
+    let mut read = 0;
+    let mut truncated = None;
+    let mut meeting = (from == to).then_some(from);
+
+    while meeting.is_none() && forward.depth + backward.depth < options.max_depth {
+        let (side, other) = if forward.level.len() <= backward.level.len() {
+            (&mut forward, &backward)
+        } else {
+            (&mut backward, &forward)
+        };
+        if side.level.is_empty() {
+            break;
+        }
+
+        let mut level = std::mem::take(&mut side.level);
+        let budget = options.limits.max_nodes.saturating_sub(read);
+        if level.len() > budget {
+            level.truncate(budget);
+            truncated = Some(Truncation::MaxNodes);
+        }
+        read += level.len();
+        debug!("shortest_path: {} side, depth {}, {} nodes", side.direction, side.depth, level.len());
+
+        let reads = stream::iter(level)
+            .map(|id| {
+                let (direction, relation_type) = (&side.direction, &side.relation_type);
+                async move {
+                    let rows = db
+                        .get_node_traversal(&id.to_string(), direction, relation_type)
+                        .await
+                        .map_err(|e| TraversalError::Read {
+                            id,
+                            message: e.to_string(),
+                        })?;
+                    Ok::<_, TraversalError>((id, rows))
+                }
+            })
+            .buffered(options.limits.concurrency)
+            .collect::<Vec<_>>();
+        let results = match timeout_at(deadline, reads).await {
+            Ok(results) => results,
+            Err(_) => {
+                truncated = Some(Truncation::Timeout);
+                break;
+            }
+        };
+
+        side.depth += 1;
+        for result in results {
+            let (id, rows) = result?;
+            for row in rows.into_iter().filter(is_relation) {
+                let next = match row.relates_to.as_deref().map(Uuid::parse_str) {
+                    Some(Ok(next)) => next,
+                    _ => continue,
+                };
+                let link = Link {
+                    relation: row.relation.unwrap_or_default(),
+                    tags: row.tags.unwrap_or_default(),
+                };
+                side.reach(id, next, link, row.name);
+            }
+        }
+
+        meeting = side.meeting(other);
+
+        if truncated.is_some() {
+            break;
+        }
+    }
+
+    let meeting = match meeting {
+        Some(meeting) => meeting,
+        None => return Err(TraversalError::NoPath { from, to, truncated }),
+    };
+
+    let steps = join(&forward, &backward, meeting);
+    let ids: Vec<Uuid> = steps.iter().map(|(id, _)| *id).collect();
+    let mut nodes = read_nodes(db, &ids).await?;
+    let names: HashMap<Uuid, String> = forward
+        .steps
+        .iter()
+        .chain(backward.steps.iter())
+        .map(|(id, s)| (*id, s.name.clone()))
+        .collect();
+
+    let mut path_nodes = vec![];
+    let mut edges = vec![];
+    for (i, (id, link)) in steps.into_iter().enumerate() {
+        if let Some(link) = link {
+            edges.push(FlatEdge {
+                source: ids[i - 1],
+                target: id,
+                relation: link.relation,
+                direction: options.direction.clone(),
+                depth: i,
+                tags: link.tags,
+            });
+        }
+        // nodes without root row only exist as relations, named after them
+        let node = nodes.remove(&id).unwrap_or_else(|| {
+            let name = names.get(&id).cloned().unwrap_or_default();
+            Node::new(id, String::new(), String::new(), name, String::new(), vec![])
+        });
+        path_nodes.push(node);
+    }
+
+    info!("shortest_path: Path of {} relations, {} nodes read", edges.len(), read);
+    Ok(PathResponse {
+        length: edges.len(),
+        nodes: path_nodes,
+        edges,
+    })
+}
+
+/// Path from the start of `forward` to the start of `backward` through the node where they met,
+/// with the relation that reaches each node. The relations of the backward side are reversed
+/// to follow the direction of the path.
+fn join(forward: &Side, backward: &Side, meeting: Uuid) -> Vec<(Uuid, Option<Link>)> {
+    let mut steps = forward.path(meeting);
+    let back = backward.path(meeting);
+    for pair in back.windows(2).rev() {
+        let link = pair[1].1.clone().unwrap_or_default();
+        let link = Link {
+            relation: reverse_relation(link.relation),
+            tags: link.tags,
+        };
+        steps.push((pair[0].0, Some(link)));
+    }
+    steps
+}
+
+fn is_relation(row: &DbRelation) -> bool {
+    row.direction.as_deref().is_some_and(|d| !d.is_empty())
+}
+
+async fn read_nodes(
+    db: &ScyllaDbService,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, Node>, TraversalError> {
+    let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    let mut nodes = HashMap::new();
+    for (id, result) in db.get_nodes(ids, false, false).await {
+        let uuid = Uuid::parse_str(&id).unwrap_or_default();
+        let rows = result.map_err(|message| TraversalError::Read { id: uuid, message })?;
+        if let Some(node) = Node::from(rows) {
+            nodes.insert(uuid, node);
+        }
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    fn link(relation: &str) -> Link {
+        Link {
+            relation: relation.to_owned(),
+            tags: vec![],
+        }
+    }
+
+    /// Side from `start` reaching each node of `chain` from the previous one, a level at a time.
+    fn side(start: u128, direction: DIR, relation: &str, chain: &[u128]) -> Side {
+        let mut side = Side::new(id(start), direction.to_string(), None);
+        let mut previous = id(start);
+        for n in chain {
+            side.depth += 1;
+            side.level.clear();
+            assert!(side.reach(previous, id(*n), link(relation), n.to_string()));
+            previous = id(*n);
+        }
+        side
+    }
+
+    fn relations(steps: &[(Uuid, Option<Link>)]) -> Vec<(Uuid, Option<String>)> {
+        steps
+            .iter()
+            .map(|(id, link)| (*id, link.as_ref().map(|l| l.relation.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn join_a_path_that_meets_in_the_middle() {
+        let forward = side(1, DIR::OUT, "ISCHILD", &[2, 3]);
+        let backward = side(5, DIR::IN, "ISPARENT", &[4, 3]);
+
+        let meeting = backward.meeting(&forward).unwrap();
+        assert_eq!(meeting, id(3));
+
+        let child = || Some("ISCHILD".to_owned());
+        assert_eq!(
+            relations(&join(&forward, &backward, meeting)),
+            vec![
+                (id(1), None),
+                (id(2), child()),
+                (id(3), child()),
+                // reached by the backward side, reversed
+                (id(4), child()),
+                (id(5), child()),
+            ]
+        );
+    }
+
+    #[test]
+    fn join_from_the_node_to_itself() {
+        let forward = Side::new(id(1), DIR::OUT.to_string(), None);
+        let backward = Side::new(id(1), DIR::IN.to_string(), None);
+
+        assert_eq!(relations(&join(&forward, &backward, id(1))), vec![(id(1), None)]);
+    }
+
+    #[test]
+    fn no_meeting_without_a_common_node() {
+        let mut forward = side(1, DIR::OUT, "ISCHILD", &[2]);
+        let backward = side(5, DIR::IN, "ISPARENT", &[4]);
+
+        assert_eq!(forward.meeting(&backward), None);
+        assert_eq!(backward.meeting(&forward), None);
+        // a node is reached once
+        assert!(!forward.reach(id(2), id(1), link("ISPARENT"), "1".to_owned()));
+    }
+
+    #[test]
+    fn meeting_is_the_closest_node_to_the_other_start() {
+        // the backward side reached 3 at depth 2 and 4 at depth 1
+        let mut backward = side(5, DIR::IN, "ISPARENT", &[4]);
+        backward.depth = 2;
+        assert!(backward.reach(id(4), id(3), link("ISPARENT"), "3".to_owned()));
+
+        let mut forward = Side::new(id(1), DIR::OUT.to_string(), None);
+        forward.depth = 1;
+        assert!(forward.reach(id(1), id(3), link("ISCHILD"), "3".to_owned()));
+        assert!(forward.reach(id(1), id(4), link("ISCHILD"), "4".to_owned()));
+
+        assert_eq!(forward.meeting(&backward), Some(id(4)));
+    }
+}